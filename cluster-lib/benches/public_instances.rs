@@ -1,3 +1,4 @@
+use cluster_lib::config::SolverConfig;
 use cluster_lib::disk::load;
 
 use cluster_lib::search::Solver;
@@ -20,7 +21,7 @@ fn exact_track(c: &mut Criterion) {
     let instances = vec![1, 3, 5, 7, 9, 11, 13, 15, 21, 23, 25, 31, 35, 41, 47];
     for instance in instances {
         let input = load(File::open(format!("../exact/exact{:03}.gr", instance)).unwrap()).unwrap();
-        let solver = Solver::new(input);
+        let solver = Solver::new(input, SolverConfig::default());
         group.bench_with_input(BenchmarkId::from_parameter(instance), &solver, |b, s| {
             b.iter_batched_ref(
                 || {
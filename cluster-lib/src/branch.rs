@@ -1,37 +1,230 @@
-use std::usize;
+use std::mem;
 
-use crate::{graph::AllFrom, search::Solver};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
+use crate::{
+    config::{Branching, ProblemVariant},
+    graph::{AllFrom, Edge},
+    matrix::{Matrix, Storage},
+    search::Solver,
+};
+
+#[derive(Clone, Copy)]
 pub enum EdgeMod {
     Merge(usize, usize),
     Cut(usize, usize),
     Nothing,
 }
 
-impl Solver {
-    // find best edge to split on in O(n + m^2) time
-    // edge is positive
-    // there is at least one conflict
-    pub fn best_edge(&mut self) -> EdgeMod {
+// picks the pair to branch on and which branch comes first. it has to be a pair that is not fixed
+// and `EdgeMod::Nothing` only when no conflict is left
+pub trait BranchingRule<S: Storage<Edge> = Matrix<Edge>>: Send + Sync {
+    fn choose(&mut self, solver: &Solver<S>) -> EdgeMod;
+    // the rule of a clone of the solver, with a state of its own
+    fn box_clone(&self) -> Box<dyn BranchingRule<S>>;
+}
+
+impl<S: Storage<Edge>> Clone for Box<dyn BranchingRule<S>> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+// the rule for `config.branching`, `Solver::rule` can be replaced by any other
+pub fn rule<S: Storage<Edge>>(branching: Branching) -> Box<dyn BranchingRule<S>> {
+    match branching {
+        Branching::MostConflicts => Box::new(MostConflicts),
+        Branching::Vertex => Box::new(VertexCluster),
+        Branching::LowerBound => Box::new(LowerBoundImpact),
+        Branching::Random(seed) => Box::new(RandomTies {
+            rng: StdRng::seed_from_u64(seed),
+        }),
+    }
+}
+
+// edit the pair first, cut an edge or merge a non-edge
+fn edit_first<S: Storage<Edge>>(solver: &Solver<S>, v1: usize, v2: usize) -> EdgeMod {
+    if solver.graph[[v1, v2]].weight > 0 {
+        EdgeMod::Cut(v1, v2)
+    } else {
+        EdgeMod::Merge(v1, v2)
+    }
+}
+
+// the free pair with the highest score above 0, the first one on ties
+fn highest<S: Storage<Edge>, F: FnMut(usize, usize) -> u64>(
+    solver: &Solver<S>,
+    mut score: F,
+) -> EdgeMod {
+    let mut best = EdgeMod::Nothing;
+    let mut best_score = 0;
+    for (i1, v1) in solver.graph.active.all(0) {
+        for (_, v2) in solver.graph.active.all(i1) {
+            if solver.graph[[v1, v2]].fixed {
+                continue;
+            }
+            let score = score(v1, v2);
+            if score > best_score {
+                best_score = score;
+                best = edit_first(solver, v1, v2);
+            }
+        }
+    }
+    best
+}
+
+// the pair in the most conflict triples
+#[derive(Clone)]
+pub struct MostConflicts;
+
+impl<S: Storage<Edge>> BranchingRule<S> for MostConflicts {
+    // find best edge to split on in O(n + m^2) time, or right away from the queue
+    fn choose(&mut self, solver: &Solver<S>) -> EdgeMod {
+        if let Some(queue) = &solver.packing.queue {
+            let graph = &solver.graph;
+            return match queue.first() {
+                Some([v1, v2]) => {
+                    debug_assert!(graph.is_active[v1] && graph.is_active[v2]);
+                    debug_assert!(!graph[[v1, v2]].fixed);
+                    edit_first(solver, v1, v2)
+                }
+                None => EdgeMod::Nothing,
+            };
+        }
+        highest(solver, |v1, v2| {
+            solver.packing.edge_conflicts[[v1, v2]] as u64
+        })
+    }
+
+    fn box_clone(&self) -> Box<dyn BranchingRule<S>> {
+        Box::new(self.clone())
+    }
+}
+
+// settle the cluster of the vertex with the most conflicts one pair at a time, joining its
+// neighbours first
+#[derive(Clone)]
+pub struct VertexCluster;
+
+impl<S: Storage<Edge>> BranchingRule<S> for VertexCluster {
+    fn choose(&mut self, solver: &Solver<S>) -> EdgeMod {
+        let graph = &solver.graph;
+        let conflicts = |v1: usize, v2: usize| {
+            if graph[[v1, v2]].fixed {
+                0
+            } else {
+                solver.packing.edge_conflicts[[v1, v2]] as u64
+            }
+        };
+        let mut best = None;
+        let mut best_total = 0;
+        for &v1 in &graph.active {
+            let total: u64 = graph
+                .active
+                .iter()
+                .filter(|&&v2| v2 != v1)
+                .map(|&v2| conflicts(v1, v2))
+                .sum();
+            if total > best_total {
+                best_total = total;
+                best = Some(v1);
+            }
+        }
+        let v1 = match best {
+            Some(v1) => v1,
+            None => return EdgeMod::Nothing,
+        };
+        let &v2 = graph
+            .active
+            .iter()
+            .filter(|&&v2| v2 != v1)
+            .max_by_key(|&&v2| (conflicts(v1, v2), graph[[v1, v2]].weight > 0))
+            .unwrap();
+        if graph[[v1, v2]].weight > 0 {
+            EdgeMod::Merge(v1, v2)
+        } else {
+            EdgeMod::Cut(v1, v2)
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn BranchingRule<S>> {
+        Box::new(self.clone())
+    }
+}
+
+// the pair whose edit costs the most beyond what the packing already counts on it, times its
+// conflicts, so the bound rises the most in the branch that edits it
+#[derive(Clone)]
+pub struct LowerBoundImpact;
+
+impl<S: Storage<Edge>> BranchingRule<S> for LowerBoundImpact {
+    fn choose(&mut self, solver: &Solver<S>) -> EdgeMod {
+        let packing = &solver.packing;
+        highest(solver, |v1, v2| {
+            let conflicts = packing.edge_conflicts[[v1, v2]] as u64;
+            let weight = solver.graph[[v1, v2]].weight.unsigned_abs();
+            let left = weight.saturating_sub(packing.edge_cost[[v1, v2]]) as u64;
+            conflicts * (left + 1)
+        })
+    }
+
+    fn box_clone(&self) -> Box<dyn BranchingRule<S>> {
+        Box::new(self.clone())
+    }
+}
+
+// the pair in the most conflict triples, with a random one of them on ties. every clone of the
+// solver draws from its own copy of the generator
+#[derive(Clone)]
+pub struct RandomTies {
+    rng: StdRng,
+}
+
+impl<S: Storage<Edge>> BranchingRule<S> for RandomTies {
+    fn choose(&mut self, solver: &Solver<S>) -> EdgeMod {
         let mut best = EdgeMod::Nothing;
         let mut best_cost = 0;
-
-        for (i1, v1) in self.graph.active.all(0) {
-            for (_, v2) in self.graph.active.all(i1) {
-                if self.graph[[v1, v2]].fixed {
+        let mut ties = 0;
+        for (i1, v1) in solver.graph.active.all(0) {
+            for (_, v2) in solver.graph.active.all(i1) {
+                if solver.graph[[v1, v2]].fixed {
                     continue;
                 }
-                let cost = self.packing.edge_conflicts[[v1, v2]];
+                let cost = solver.packing.edge_conflicts[[v1, v2]];
                 if cost > best_cost {
                     best_cost = cost;
-                    if self.graph[[v1, v2]].weight > 0 {
-                        best = EdgeMod::Cut(v1, v2)
-                    } else {
-                        best = EdgeMod::Merge(v1, v2)
+                    ties = 0;
+                }
+                // every pair of the best cost so far stays with the same chance
+                if cost == best_cost && cost > 0 {
+                    ties += 1;
+                    if self.rng.gen_range(0..ties) == 0 {
+                        best = edit_first(solver, v1, v2);
                     }
                 }
             }
         }
         best
     }
+
+    fn box_clone(&self) -> Box<dyn BranchingRule<S>> {
+        Box::new(self.clone())
+    }
+}
+
+impl<S: Storage<Edge>> Solver<S> {
+    // deletion needs no rule of its own, `Graph::restrict` fixed the non-edges so the rules only
+    // cut or keep edges. completion has nothing to choose, `new` already joined the components
+    pub fn best_edge(&mut self) -> EdgeMod {
+        match self.config.variant {
+            ProblemVariant::Completion => EdgeMod::Nothing,
+            _ => {
+                // the rule looks at the solver, so it is taken out while it chooses
+                let mut rule = mem::replace(&mut self.rule, Box::new(MostConflicts));
+                let edge = rule.choose(self);
+                self.rule = rule;
+                edge
+            }
+        }
+    }
 }
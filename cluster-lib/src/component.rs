@@ -1,4 +1,7 @@
-use crate::graph::{AllFrom, Graph};
+use crate::{
+    graph::{AllFrom, Edge, Graph},
+    matrix::Storage,
+};
 
 #[derive(Clone)]
 pub struct Components {
@@ -10,26 +13,49 @@ impl Components {
     pub fn new(len: usize) -> Self {
         Self {
             un_active: vec![],
-            vertex_markers: vec![false; len * len],
+            vertex_markers: vec![false; len],
         }
     }
 
-    pub fn isolate_component(&mut self, graph: &mut Graph) -> usize {
+    // the last `count` vertices that were set aside
+    pub fn aside(&self, count: usize) -> &[usize] {
+        &self.un_active[self.un_active.len() - count..]
+    }
+
+    // whether other components are set aside from the active vertices
+    pub fn set_aside(&self) -> bool {
+        !self.un_active.is_empty()
+    }
+
+    pub fn isolate_component<S: Storage<Edge>>(&mut self, graph: &mut Graph<S>) -> usize {
+        if graph.active.is_empty() {
+            return 0;
+        }
         for (_, v) in graph.active.all(0) {
             self.vertex_markers[v] = false;
         }
         add_connected(graph, graph.active[0], &mut self.vertex_markers);
         let mut count = 0;
         for i in (0..graph.active.len()).rev() {
-            if !self.vertex_markers[graph.active[i]] {
+            let v = graph.active[i];
+            if !self.vertex_markers[v] {
                 self.un_active.push(graph.active.swap_remove(i));
+                graph.is_active[v] = false;
                 count += 1;
             }
         }
         count
     }
 
-    pub fn other_component(&mut self, active: &mut Vec<usize>, count: usize) -> usize {
+    pub fn other_component<S: Storage<Edge>>(
+        &mut self,
+        graph: &mut Graph<S>,
+        count: usize,
+    ) -> usize {
+        for &v in &graph.active {
+            graph.is_active[v] = false;
+        }
+        let active = &mut graph.active;
         let active_len = active.len();
         let un_active_len = self.un_active.len();
         if count < active_len {
@@ -40,19 +66,29 @@ impl Components {
             self.un_active[un_active_len - count..split].swap_with_slice(active);
             active.extend(self.un_active.drain(split..))
         }
+        for &v in &graph.active {
+            graph.is_active[v] = true;
+        }
         active_len
     }
 
-    pub fn all_components(&mut self, active: &mut Vec<usize>, count: usize) {
-        active.extend(self.un_active.drain(self.un_active.len() - count..));
+    pub fn all_components<S: Storage<Edge>>(&mut self, graph: &mut Graph<S>, count: usize) {
+        for v in self.un_active.drain(self.un_active.len() - count..) {
+            graph.activate(v);
+        }
     }
 }
 
-fn add_connected(graph: &Graph, v1: usize, vertex_markers: &mut Vec<bool>) {
+// mark the component of `v1`, with a stack so large components do not overflow the call stack
+fn add_connected<S: Storage<Edge>>(graph: &Graph<S>, v1: usize, vertex_markers: &mut [bool]) {
     vertex_markers[v1] = true;
-    for (_, v2) in graph.positive(v1, 0) {
-        if !vertex_markers[v2] {
-            add_connected(graph, v2, vertex_markers);
+    let mut stack = vec![v1];
+    while let Some(v1) = stack.pop() {
+        for v2 in graph.positive(v1) {
+            if !vertex_markers[v2] {
+                vertex_markers[v2] = true;
+                stack.push(v2);
+            }
         }
     }
 }
@@ -0,0 +1,137 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictCost {
+    // every conflict triple counts once for each of its pairs
+    Count,
+    // a pair scores the cheapest way to resolve the triple without editing it
+    MinWeight,
+    // every pair scores the cost of the cheapest deletion in the triple
+    Deletion,
+}
+
+// where the lower bound from the lp relaxation is added to the packing. the relaxation has a
+// variable for every free pair in a conflict triple, a graph with more than
+// `SolverConfig::lp_pairs` of them gets no bound. no more cuts are added once the tableau has
+// `SolverConfig::lp_entries` entries, and every relaxation stops after `SolverConfig::lp_pivots`
+// pivots with the bound it has by then
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LpBound {
+    Off,
+    // only for the input graph, which can prove the heuristic optimal
+    Root,
+    // also after every merge and cut, much stronger but slow
+    Nodes,
+}
+
+// when the packing is improved by swapping its triples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Improvement {
+    Off,
+    Root,
+    // at the root and then every this many nodes
+    Every(u64),
+}
+
+// the built-in rules for the pair to branch on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Branching {
+    // the pair in the most conflict triples
+    MostConflicts,
+    // settle the cluster of one vertex at a time
+    Vertex,
+    // conflicts weighted by what the packing does not count of the pair yet
+    LowerBound,
+    // the most conflicts, with ties broken by a random generator of this seed
+    Random(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemVariant {
+    // edges can be deleted and inserted
+    Editing,
+    // only deletions, so non-edges are forbidden
+    Deletion,
+    // only insertions, so every connected component becomes a cluster
+    Completion,
+}
+
+// the number of clusters in the result, completion ignores it and the maximum cluster size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterCount {
+    Any,
+    AtMost(usize),
+    Exactly(usize),
+}
+
+impl ClusterCount {
+    pub fn min(self) -> usize {
+        match self {
+            ClusterCount::Exactly(count) => count,
+            _ => 0,
+        }
+    }
+
+    pub fn max(self) -> usize {
+        match self {
+            ClusterCount::Any => usize::MAX,
+            ClusterCount::AtMost(count) | ClusterCount::Exactly(count) => count,
+        }
+    }
+
+    pub fn fits(self, count: usize) -> bool {
+        (self.min()..=self.max()).contains(&count)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolverConfig {
+    pub variant: ProblemVariant,
+    pub conflict_cost: ConflictCost,
+    // update the packing after every branch instead of repacking from scratch
+    pub incremental: bool,
+    pub lower_bound: bool,
+    pub lp_bound: LpBound,
+    // the limits of the lp relaxation, see `LpBound`
+    pub lp_pairs: usize,
+    pub lp_entries: usize,
+    pub lp_pivots: usize,
+    pub improve_packing: Improvement,
+    pub branching: Branching,
+    // split the graph into components after every branch
+    pub branch_components: bool,
+    // pivot rounds for the initial upper bound, 0 starts from deleting every edge
+    pub heuristic_rounds: usize,
+    // solve the decision problem for increasing costs, starting at the lower bound
+    pub deepening: bool,
+    // search the branches on this many threads
+    pub threads: usize,
+    // components are neither branched on nor solved separately with a bound on the count
+    pub cluster_count: ClusterCount,
+    // the most original vertices in one cluster
+    pub max_cluster_size: Option<usize>,
+    // measure the time spent updating the packing for the statistics, which costs time itself
+    pub time_packing: bool,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self {
+            variant: ProblemVariant::Editing,
+            conflict_cost: ConflictCost::Count,
+            incremental: true,
+            lower_bound: true,
+            lp_bound: LpBound::Off,
+            lp_pairs: 250,
+            lp_entries: 1 << 18,
+            lp_pivots: 2000,
+            improve_packing: Improvement::Off,
+            branching: Branching::MostConflicts,
+            branch_components: false,
+            heuristic_rounds: 10,
+            deepening: false,
+            threads: 1,
+            cluster_count: ClusterCount::Any,
+            max_cluster_size: None,
+            time_packing: false,
+        }
+    }
+}
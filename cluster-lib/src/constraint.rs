@@ -0,0 +1,94 @@
+use std::{
+    cmp::max,
+    error::Error,
+    fmt::{self, Display},
+};
+
+use crate::{
+    config::ProblemVariant,
+    graph::{Edge, Graph},
+    matrix::Storage,
+};
+
+// pairs of original vertices, numbered from 0
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Constraints {
+    // pairs that end up in the same cluster
+    pub must_link: Vec<(usize, usize)>,
+    // pairs that end up in different clusters
+    pub cannot_link: Vec<(usize, usize)>,
+}
+
+// the pair has to end up both in one cluster and in different clusters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contradiction {
+    pub v1: usize,
+    pub v2: usize,
+}
+
+impl Display for Contradiction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} and {} can not be linked and separated at the same time",
+            self.v1 + 1,
+            self.v2 + 1
+        )
+    }
+}
+
+impl Error for Contradiction {}
+
+impl<S: Storage<Edge>> Graph<S> {
+    // merge the must-link pairs and forbid the cannot-link pairs before solving, returns the cost
+    // of the edits this takes. like with the kernel, solutions of the result also cluster the
+    // original vertices through `root`
+    pub fn constrain(&mut self, constraints: &Constraints) -> Result<u32, Contradiction> {
+        let mut cost = 0;
+        for &(v1, v2) in &constraints.must_link {
+            let (r1, r2) = (self.root(v1), self.root(v2));
+            if r1 == r2 {
+                continue;
+            }
+            if self[[r1, r2]].fixed {
+                return Err(Contradiction { v1, v2 });
+            }
+            cost += self.merge(r1, r2).1;
+        }
+        for &(v1, v2) in &constraints.cannot_link {
+            let (r1, r2) = (self.root(v1), self.root(v2));
+            if r1 == r2 {
+                return Err(Contradiction { v1, v2 });
+            }
+            cost += max(0, self[[r1, r2]].weight) as u32;
+            self[[r1, r2]] = Edge::none();
+        }
+        Ok(cost)
+    }
+}
+
+impl<S: Storage<Edge>> Graph<S> {
+    // forbid the pairs that can not be edited in the variant. only deletion forbids anything,
+    // completion is solved by `Solver::new` directly
+    pub fn restrict(&mut self, variant: ProblemVariant) {
+        if variant != ProblemVariant::Deletion {
+            return;
+        }
+        let forbid = |edge: Edge| {
+            if edge.weight > 0 {
+                edge
+            } else {
+                Edge::none()
+            }
+        };
+        for v1 in self.active.clone() {
+            let stored: Vec<_> = (self.edges.neighbours(v1))
+                .filter(|&v2| v1 < v2 && self.is_active[v2])
+                .collect();
+            for v2 in stored {
+                self[[v1, v2]] = forbid(self[[v1, v2]]);
+            }
+        }
+        self.edges.map_defaults(forbid);
+    }
+}
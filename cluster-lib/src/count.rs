@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    branch::EdgeMod,
+    config::{ClusterCount, SolverConfig},
+    graph::{AllFrom, Edge, Graph},
+    heuristic::closure,
+    matrix::Storage,
+    search::Solver,
+};
+
+// the bounds on the number and the size of the clusters
+
+// the size of every cluster, by label
+fn cluster_sizes<S: Storage<Edge>>(graph: &Graph<S>, clusters: &[usize]) -> BTreeMap<usize, usize> {
+    let mut sizes = BTreeMap::new();
+    for &v in &graph.active {
+        *sizes.entry(clusters[v]).or_insert(0) += graph.size[v];
+    }
+    sizes
+}
+
+// whether the clusters fit the bounds of `config`
+pub fn fits<S: Storage<Edge>>(graph: &Graph<S>, clusters: &[usize], config: &SolverConfig) -> bool {
+    let sizes = cluster_sizes(graph, clusters);
+    let max_size = config.max_cluster_size.unwrap_or(usize::MAX);
+    config.cluster_count.fits(sizes.len()) && sizes.values().all(|&size| size <= max_size)
+}
+
+// take the vertex that is held the least by its cluster out of it, only from cluster `from` if
+// given. returns false when every cluster has a single vertex
+fn split_off<S: Storage<Edge>>(
+    graph: &Graph<S>,
+    clusters: &mut [usize],
+    from: Option<usize>,
+) -> bool {
+    let mut best = None;
+    for (_, v1) in graph.active.all(0) {
+        if from.is_some_and(|from| clusters[v1] != from) {
+            continue;
+        }
+        let mut held = 0i64;
+        let mut alone = true;
+        for (_, v2) in graph.active.all(0) {
+            if v1 != v2 && clusters[v1] == clusters[v2] {
+                held += graph[[v1, v2]].weight as i64;
+                alone = false;
+            }
+        }
+        if !alone && best.is_none_or(|(_, best_held)| held < best_held) {
+            best = Some((v1, held));
+        }
+    }
+    let v1 = match best {
+        Some((v1, _)) => v1,
+        None => return false,
+    };
+    // the label is a vertex id, so `v1` can take its own once the others give it up
+    let old = clusters[v1];
+    if old == v1 {
+        let &v2 = graph
+            .active
+            .iter()
+            .find(|&&v2| v2 != v1 && clusters[v2] == old)
+            .unwrap();
+        for cluster in clusters.iter_mut() {
+            if *cluster == old {
+                *cluster = v2;
+            }
+        }
+    }
+    clusters[v1] = v1;
+    true
+}
+
+// join or split the clusters greedily until they fit the bounds of `config`, returns false when
+// they can not
+pub fn fit<S: Storage<Edge>>(
+    graph: &Graph<S>,
+    clusters: &mut [usize],
+    config: &SolverConfig,
+) -> bool {
+    let (count, max_size) = (config.cluster_count, config.max_cluster_size);
+    let max_size = max_size.unwrap_or(usize::MAX);
+    loop {
+        let sizes = cluster_sizes(graph, clusters);
+        if let Some((&large, _)) = sizes.iter().find(|&(_, &size)| size > max_size) {
+            if !split_off(graph, clusters, Some(large)) {
+                return false;
+            }
+        } else if sizes.len() > count.max() {
+            // join the two clusters with the most weight between them that fit together
+            let mut between = BTreeMap::new();
+            for (i1, v1) in graph.active.all(0) {
+                for (_, v2) in graph.active.all(i1) {
+                    let (c1, c2) = (clusters[v1], clusters[v2]);
+                    if c1 != c2 && sizes[&c1] + sizes[&c2] <= max_size {
+                        *between.entry((c1.min(c2), c1.max(c2))).or_insert(0i64) +=
+                            graph[[v1, v2]].weight as i64;
+                    }
+                }
+            }
+            let (c1, c2) = match between.iter().max_by_key(|&(_, &weight)| weight) {
+                Some((&pair, _)) => pair,
+                None => return false,
+            };
+            for cluster in clusters.iter_mut() {
+                if *cluster == c2 {
+                    *cluster = c1;
+                }
+            }
+        } else if sizes.len() < count.min() {
+            if !split_off(graph, clusters, None) {
+                return false;
+            }
+        } else {
+            return true;
+        }
+    }
+}
+
+impl<S: Storage<Edge>> Solver<S> {
+    // whether the vertices fit in one cluster
+    pub fn fits_size(&self, v1: usize, v2: usize) -> bool {
+        let size = self.graph.size[v1] + self.graph.size[v2];
+        self.config.max_cluster_size.is_none_or(|max| size <= max)
+    }
+
+    // a lower bound on the cost of fitting the bounds, on top of the packing. `None` when they
+    // can not be reached anymore
+    pub fn fit_lower(&self) -> Option<u32> {
+        let count = self.config.cluster_count;
+        let graph = &self.graph;
+        if let Some(max) = self.config.max_cluster_size {
+            let total: usize = graph.active.iter().map(|&v| graph.size[v]).sum();
+            if graph.active.iter().any(|&v| graph.size[v] > max)
+                || count.max() < total.div_ceil(max.max(1))
+            {
+                return None;
+            }
+        }
+        if count == ClusterCount::Any {
+            return Some(0);
+        }
+        if graph.active.len() < count.min() {
+            return None;
+        }
+
+        // vertices with forbidden pairs among each other all need a cluster of their own
+        let mut separated: Vec<usize> = vec![];
+        for &v1 in &graph.active {
+            if separated.iter().all(|&v2| graph[[v1, v2]].fixed) {
+                separated.push(v1);
+            }
+        }
+        if separated.len() > count.max() {
+            return None;
+        }
+
+        // clusters only get fewer than the components of the edges by inserting pairs between
+        // them, which are in no conflict triple. every join takes at least one of them
+        let components = closure(graph);
+        let joins = cluster_sizes(graph, &components)
+            .len()
+            .saturating_sub(count.max());
+        if joins == 0 {
+            return Some(0);
+        }
+        let mut cheapest = None;
+        for (i1, v1) in graph.active.all(0) {
+            for (_, v2) in graph.active.all(i1) {
+                let edge = graph[[v1, v2]];
+                if !edge.fixed && components[v1] != components[v2] {
+                    let cost = edge.weight.unsigned_abs();
+                    cheapest = Some(cheapest.map_or(cost, |cheapest: u32| cheapest.min(cost)));
+                }
+            }
+        }
+        cheapest.map(|cost| cost.saturating_mul(joins as u32))
+    }
+
+    // the pair to branch on at a leaf whose clusters do not fit the bounds
+    pub fn fit_edge(&self) -> EdgeMod {
+        let count = self.config.cluster_count;
+        let max_size = self.config.max_cluster_size.unwrap_or(usize::MAX);
+        if count == ClusterCount::Any && max_size == usize::MAX {
+            return EdgeMod::Nothing;
+        }
+        let clusters = self.leaf_clusters();
+        let sizes = cluster_sizes(&self.graph, &clusters);
+        let large = |v: usize| sizes[&clusters[v]] > max_size;
+        let mut best = EdgeMod::Nothing;
+        let mut best_cost = u32::MAX;
+        for (i1, v1) in self.graph.active.all(0) {
+            for (_, v2) in self.graph.active.all(i1) {
+                let edge = self.graph[[v1, v2]];
+                let joined = clusters[v1] == clusters[v2];
+                if edge.fixed || edge.weight.unsigned_abs() >= best_cost {
+                    continue;
+                }
+                if joined && (large(v1) || sizes.len() < count.min()) {
+                    best = EdgeMod::Cut(v1, v2);
+                } else if !joined
+                    && sizes.len() > count.max()
+                    && sizes.values().all(|&s| s <= max_size)
+                {
+                    best = EdgeMod::Merge(v1, v2);
+                } else {
+                    continue;
+                }
+                best_cost = edge.weight.unsigned_abs();
+            }
+        }
+        best
+    }
+}
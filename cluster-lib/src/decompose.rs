@@ -0,0 +1,192 @@
+use rayon::prelude::*;
+
+use crate::{
+    config::{ClusterCount, SolverConfig},
+    graph::{Edge, Graph},
+    matrix::{Matrix, Storage},
+    search::Solver,
+    stats::SolverStats,
+    verify::Violation,
+};
+
+// a connected component of the positive edges as an instance of its own
+pub struct Component<S: Storage<Edge> = Matrix<Edge>> {
+    pub graph: Graph<S>,
+    // the vertex of the original graph for every vertex of `graph`
+    pub vertices: Vec<usize>,
+}
+
+impl<S: Storage<Edge>> Component<S> {
+    pub fn new(graph: &Graph<S>, vertices: Vec<usize>) -> Self {
+        let size = vertices.len();
+        // the vertices keep their sizes, so the pairs without a value keep their default
+        let base = graph.edges.base().unwrap_or_else(|| Edge::new(-1));
+        let mut component = Graph::<S>::filled(size, base.weight);
+        // the defaults only depend on the sizes, so one earlier vertex of every size is enough
+        let mut sizes = vec![];
+        for (i, &v) in vertices.iter().enumerate() {
+            component
+                .edges
+                .set_size(i, graph.size[v], &sizes, |edge, product| {
+                    edge.scale(product)
+                });
+            if !sizes.iter().any(|&i2| component.size[i2] == graph.size[v]) {
+                sizes.push(i);
+            }
+            component.size[i] = graph.size[v];
+        }
+        // like in `load`, the merged vertices need their diagonal too
+        for i in 0..size * 2 {
+            component[[i, i]] = Edge::new(i32::MAX);
+        }
+        let mut index = vec![usize::MAX; graph.vertex_merged.len()];
+        for (i, &v) in vertices.iter().enumerate() {
+            index[v] = i;
+        }
+        for (i1, &v1) in vertices.iter().enumerate() {
+            for v2 in graph.edges.neighbours(v1) {
+                if index[v2] != usize::MAX && i1 < index[v2] {
+                    component[[i1, index[v2]]] = graph[[v1, v2]];
+                }
+            }
+        }
+        Self {
+            graph: component,
+            vertices,
+        }
+    }
+}
+
+// the components of the active vertices of `graph`, numbered from 0 in the order of `active`
+pub fn split<S: Storage<Edge>>(graph: &Graph<S>) -> Vec<Component<S>> {
+    (connected(graph, &graph.active).into_iter())
+        .map(|vertices| Component::new(graph, vertices))
+        .collect()
+}
+
+// the vertices of the components that contain `from`, each one starting with the first of its
+// vertices in `from`
+pub fn connected<S: Storage<Edge>>(graph: &Graph<S>, from: &[usize]) -> Vec<Vec<usize>> {
+    let mut visited = vec![false; graph.vertex_merged.len()];
+    let mut components = vec![];
+    for &v1 in from {
+        if visited[v1] {
+            continue;
+        }
+        visited[v1] = true;
+        let mut vertices = vec![v1];
+        let mut next = 0;
+        while let Some(&v2) = vertices.get(next) {
+            next += 1;
+            for v3 in graph.positive(v2) {
+                if !visited[v3] {
+                    visited[v3] = true;
+                    vertices.push(v3);
+                }
+            }
+        }
+        components.push(vertices);
+    }
+    components
+}
+
+// combine the clusterings of all components into a clustering of `graph`
+pub fn stitch<S: Storage<Edge>>(
+    graph: &Graph<S>,
+    components: &[Component<S>],
+    solutions: &[Graph],
+) -> Graph {
+    let mut clusters = vec![usize::MAX; graph.vertex_merged.len()];
+    for (component, solution) in components.iter().zip(solutions) {
+        for (v, &original) in component.vertices.iter().enumerate() {
+            clusters[original] = component.vertices[solution.root(v)];
+        }
+    }
+    Graph::from_clusters(graph, &clusters)
+}
+
+// solve every component with its own solver, on `config.threads` threads.
+// returns the clustering of `graph`, its cost and the statistics of all solvers together. the cost
+// is `u32::MAX` when there is no solution
+pub fn solve_components<S: Storage<Edge>>(
+    graph: &Graph<S>,
+    config: SolverConfig,
+) -> (Graph, u32, SolverStats) {
+    solve_each(graph, config, None).expect("only a start can be violated")
+}
+
+// like `solve_components`, every solver starts from its part of a clustering of the original
+// vertices of `graph`, as with `Solver::with_initial_solution`
+pub fn solve_components_from<S: Storage<Edge>>(
+    graph: &Graph<S>,
+    config: SolverConfig,
+    clusters: &[usize],
+) -> Result<(Graph, u32, SolverStats), Violation> {
+    solve_each(graph, config, Some(clusters))
+}
+
+fn solve_each<S: Storage<Edge>>(
+    graph: &Graph<S>,
+    config: SolverConfig,
+    clusters: Option<&[usize]>,
+) -> Result<(Graph, u32, SolverStats), Violation> {
+    // the cluster count is shared by all components
+    if config.cluster_count != ClusterCount::Any {
+        let mut solver = Solver::new(graph.clone(), config);
+        if let Some(clusters) = clusters {
+            solver = solver.with_initial_solution(clusters)?;
+        }
+        solver.solve();
+        return Ok((solver.best, solver.upper, solver.stats));
+    }
+    // checked on the whole graph, so the violations name its vertices
+    let labels = match clusters {
+        Some(clusters) => Some(graph.root_labels(clusters, config.variant)?),
+        None => None,
+    };
+    let components = split(graph);
+    let solve = |component: &Component<S>| -> Result<_, Violation> {
+        let config = SolverConfig {
+            threads: 1,
+            ..config
+        };
+        let mut solver = Solver::new(component.graph.clone(), config);
+        if let Some(labels) = &labels {
+            let part: Vec<_> = component.vertices.iter().map(|&v| labels[v]).collect();
+            solver = solver.with_initial_solution(&part)?;
+        }
+        solver.solve();
+        Ok((solver.best, solver.upper, solver.stats))
+    };
+    let results: Vec<_> = if config.threads > 1 {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            components
+                .par_iter()
+                .map(solve)
+                .collect::<Result<_, Violation>>()
+        })?
+    } else {
+        components
+            .iter()
+            .map(solve)
+            .collect::<Result<_, Violation>>()?
+    };
+
+    let mut solutions = vec![];
+    let mut cost: u32 = 0;
+    let mut stats = SolverStats::default();
+    for (best, upper, component_stats) in results {
+        solutions.push(best);
+        // a component without a solution leaves the whole graph without one
+        cost = cost.saturating_add(upper);
+        stats.add(&component_stats);
+        stats.root_lower = stats.root_lower.saturating_add(component_stats.root_lower);
+    }
+    // the improvements of a component are not costs of the whole graph
+    stats.improvements.clear();
+    Ok((stitch(graph, &components, &solutions), cost, stats))
+}
@@ -1,122 +1,377 @@
 use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Display},
     io::{self, BufRead, BufReader, BufWriter, Read, Write},
-    usize,
+    iter::Enumerate,
 };
 
-use crate::graph::{AllFrom, Graph};
-
-pub fn load<F: Read>(file: F) -> io::Result<Graph> {
-    let mut reader = BufReader::new(file);
-    let v = loop {
-        let mut line = String::new();
-        reader.read_line(&mut line)?;
-        let mut words = line.split(' ');
-        match words.next() {
-            Some("c") => continue,
-            Some("p") => {
-                assert_eq!(words.next(), Some("cep"));
-                break words.next().unwrap().parse::<usize>().unwrap();
-            }
-            _ => return Err(io::ErrorKind::InvalidInput.into()),
+use crate::{
+    constraint::Constraints,
+    graph::{AllFrom, Edge, Graph},
+    matrix::{Sparse, Storage},
+    verify::{self, Violation},
+};
+
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    BadHeader { line: usize, text: String },
+    WrongProblem { line: usize, text: String },
+    NotANumber { line: usize, text: String },
+    BadWeight { line: usize, text: String },
+    MalformedLine { line: usize, text: String },
+    VertexOutOfRange { line: usize, text: String },
+    SelfLoop { line: usize, text: String },
+    DuplicateEdge { line: usize, text: String },
+    EdgeCount { expected: usize, found: usize },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "could not read input: {}", err),
+            ParseError::BadHeader { line, text } => {
+                write!(
+                    f,
+                    "line {}: expected `p cep n m` or `p wcep n m [d]`, found `{}`",
+                    line, text
+                )
+            }
+            ParseError::WrongProblem { line, text } => {
+                write!(f, "line {}: unknown problem descriptor `{}`", line, text)
+            }
+            ParseError::NotANumber { line, text } => {
+                write!(f, "line {}: `{}` is not a number", line, text)
+            }
+            ParseError::BadWeight { line, text } => {
+                write!(f, "line {}: weight out of range in `{}`", line, text)
+            }
+            ParseError::MalformedLine { line, text } => {
+                write!(f, "line {}: malformed edge line `{}`", line, text)
+            }
+            ParseError::VertexOutOfRange { line, text } => {
+                write!(f, "line {}: vertex out of range in `{}`", line, text)
+            }
+            ParseError::SelfLoop { line, text } => {
+                write!(f, "line {}: self loop `{}`", line, text)
+            }
+            ParseError::DuplicateEdge { line, text } => {
+                write!(f, "line {}: duplicate edge `{}`", line, text)
+            }
+            ParseError::EdgeCount { expected, found } => {
+                write!(f, "header promises {} edges, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> Self {
+        ParseError::Io(err)
+    }
+}
+
+fn parse_number(word: &str, line: usize, text: &str) -> Result<usize, ParseError> {
+    word.parse().map_err(|_| ParseError::NotANumber {
+        line,
+        text: text.to_owned(),
+    })
+}
+
+// the largest weight of a pair, the extremes are reserved for fixed pairs. sums of weights
+// saturate instead, at this weight for merged vertices and at `u32::MAX` for costs, so a
+// clustering that costs that much can not be told from no clustering
+pub const MAX_WEIGHT: i32 = i32::MAX - 1;
+
+fn parse_weight(word: &str, line: usize, text: &str) -> Result<i32, ParseError> {
+    let weight: i32 = word.parse().map_err(|_| ParseError::NotANumber {
+        line,
+        text: text.to_owned(),
+    })?;
+    if weight.unsigned_abs() > MAX_WEIGHT as u32 {
+        return Err(ParseError::BadWeight {
+            line,
+            text: text.to_owned(),
+        });
+    }
+    Ok(weight)
+}
+
+fn parse_vertex(word: &str, v: usize, line: usize, text: &str) -> Result<usize, ParseError> {
+    let index = parse_number(word, line, text)?;
+    if (1..=v).contains(&index) {
+        Ok(index - 1)
+    } else {
+        Err(ParseError::VertexOutOfRange {
+            line,
+            text: text.to_owned(),
+        })
+    }
+}
+
+// `p cep n m` lists the edges as `u v`, every other pair is a non-edge.
+// `p wcep n m [d]` lists pairs as `u v w`, where a positive weight is the cost of
+// deleting the edge and a negative weight the cost of inserting it.
+// unlisted pairs get weight `d`, which defaults to -1. weights are at most `MAX_WEIGHT`.
+pub fn load<F: Read>(file: F) -> Result<Graph, ParseError> {
+    let mut lines = BufReader::new(file).lines().enumerate();
+    let header = read_header(&mut lines)?;
+    read_pairs(lines, header)
+}
+
+// like `load`, but with the sparse backend
+pub fn load_sparse<F: Read>(file: F) -> Result<Graph<Sparse<Edge>>, ParseError> {
+    let mut lines = BufReader::new(file).lines().enumerate();
+    let header = read_header(&mut lines)?;
+    read_pairs(lines, header)
+}
+
+pub const SPARSE_SIZE: usize = 4096;
+
+// a graph with the backend that fits its size
+pub enum Instance {
+    Dense(Graph),
+    Sparse(Graph<Sparse<Edge>>),
+}
+
+// like `load`, graphs with more than `SPARSE_SIZE` vertices use the sparse backend
+pub fn load_any<F: Read>(file: F) -> Result<Instance, ParseError> {
+    let mut lines = BufReader::new(file).lines().enumerate();
+    let header = read_header(&mut lines)?;
+    if header.0 > SPARSE_SIZE {
+        read_pairs(lines, header).map(Instance::Sparse)
+    } else {
+        read_pairs(lines, header).map(Instance::Dense)
+    }
+}
+
+type Lines<F> = Enumerate<io::Lines<BufReader<F>>>;
+
+// the number of vertices and edges, whether the pairs are weighted and their default weight
+fn read_header<F: Read>(lines: &mut Lines<F>) -> Result<(usize, usize, bool, i32), ParseError> {
+    loop {
+        let (line, text) = match lines.next() {
+            Some((i, text)) => (i + 1, text?),
+            None => {
+                return Err(ParseError::BadHeader {
+                    line: 0,
+                    text: String::new(),
+                })
+            }
+        };
+        let bad_header = || ParseError::BadHeader {
+            line,
+            text: text.trim().to_owned(),
+        };
+        let words: Vec<_> = text.split_whitespace().collect();
+        match words.first() {
+            Some(&"c") => continue,
+            Some(&"p") => {
+                let weighted = match words.get(1) {
+                    Some(&"cep") => false,
+                    Some(&"wcep") => true,
+                    Some(word) => {
+                        return Err(ParseError::WrongProblem {
+                            line,
+                            text: word.to_string(),
+                        })
+                    }
+                    None => return Err(bad_header()),
+                };
+                if !matches!(words.len(), 4 | 5) {
+                    return Err(bad_header());
+                }
+                let v = parse_number(words[2], line, text.trim())?;
+                let m = parse_number(words[3], line, text.trim())?;
+                let default = match words[..] {
+                    [_, _, _, _] => -1,
+                    [_, _, _, _, word] if weighted => parse_weight(word, line, text.trim())?,
+                    _ => return Err(bad_header()),
+                };
+                return Ok((v, m, weighted, default));
+            }
+            _ => return Err(bad_header()),
         }
-    };
+    }
+}
 
-    let mut graph: Graph = Graph::new(v);
+fn read_pairs<S: Storage<Edge>, F: Read>(
+    lines: Lines<F>,
+    (v, m, weighted, default): (usize, usize, bool, i32),
+) -> Result<Graph<S>, ParseError> {
+    let mut graph = Graph::<S>::filled(v, default);
     for i in 0..v * 2 {
         graph[[i, i]].weight = i32::MAX;
     }
 
-    for line in reader.lines() {
-        let line = line?;
-        let mut words = line.split(' ');
-        match words.next() {
-            Some("c") => continue,
-            Some(word) => {
-                let v1 = word.parse::<usize>().unwrap() - 1;
-                let v2 = words.next().unwrap().parse::<usize>().unwrap() - 1;
-                graph[[v1, v2]].weight = 1;
+    let mut listed = HashSet::new();
+    let mut found = 0;
+    for (i, text) in lines {
+        let (line, text) = (i + 1, text?);
+        let text = text.trim();
+        let words: Vec<_> = text.split_whitespace().collect();
+        match words[..] {
+            [] | ["c", ..] => continue,
+            [word1, word2] | [word1, word2, _] => {
+                let weight = match words[..] {
+                    [_, _] if !weighted => 1,
+                    [_, _, word3] if weighted => parse_weight(word3, line, text)?,
+                    _ => {
+                        return Err(ParseError::MalformedLine {
+                            line,
+                            text: text.to_owned(),
+                        })
+                    }
+                };
+                let v1 = parse_vertex(word1, v, line, text)?;
+                let v2 = parse_vertex(word2, v, line, text)?;
+                if v1 == v2 {
+                    return Err(ParseError::SelfLoop {
+                        line,
+                        text: text.to_owned(),
+                    });
+                }
+                if !listed.insert((v1.min(v2), v1.max(v2))) {
+                    return Err(ParseError::DuplicateEdge {
+                        line,
+                        text: text.to_owned(),
+                    });
+                }
+                graph[[v1, v2]].weight = weight;
+                found += 1;
+            }
+            _ => {
+                return Err(ParseError::MalformedLine {
+                    line,
+                    text: text.to_owned(),
+                })
             }
-            None => return Err(io::ErrorKind::InvalidInput.into()),
         }
     }
 
+    if found != m {
+        return Err(ParseError::EdgeCount { expected: m, found });
+    }
+
     // graph.add_indirect_edges();
 
     Ok(graph)
 }
 
-impl Graph {
+// lines `must u v` and `cannot u v` for the vertices of `graph`, numbered from 1
+pub fn load_constraints<S: Storage<Edge>, F: Read>(
+    graph: &Graph<S>,
+    file: F,
+) -> Result<Constraints, ParseError> {
+    let v = graph.vertex_merged.len() / 2;
+    let mut constraints = Constraints::default();
+    for (i, text) in BufReader::new(file).lines().enumerate() {
+        let (line, text) = (i + 1, text?);
+        let text = text.trim();
+        let words: Vec<_> = text.split_whitespace().collect();
+        let (pairs, word1, word2) = match words[..] {
+            [] | ["c", ..] => continue,
+            ["must", word1, word2] => (&mut constraints.must_link, word1, word2),
+            ["cannot", word1, word2] => (&mut constraints.cannot_link, word1, word2),
+            _ => {
+                return Err(ParseError::MalformedLine {
+                    line,
+                    text: text.to_owned(),
+                })
+            }
+        };
+        let v1 = parse_vertex(word1, v, line, text)?;
+        let v2 = parse_vertex(word2, v, line, text)?;
+        if v1 == v2 {
+            return Err(ParseError::SelfLoop {
+                line,
+                text: text.to_owned(),
+            });
+        }
+        pairs.push((v1, v2));
+    }
+    Ok(constraints)
+}
+
+impl<S: Storage<Edge>> Graph<S> {
     pub fn edge_count(&self) -> u32 {
         let mut total = 0;
-        for (i1, v1) in self.active.all(0) {
-            for (_, v2) in self.active.all(i1) {
-                total += (self[[v1, v2]].weight > 0) as u32
-            }
+        for &v1 in &self.active {
+            total += self.positive(v1).filter(|&v2| v1 < v2).count() as u32;
         }
         total
     }
 
-    pub fn check_easy(&self) {
-        for (i1, v1) in self.active.all(0) {
-            for (_, v2) in self.positive(v1, i1) {
-                let mut num = 0;
-                for _ in self.conflict_edges(v1, v2, 0) {
-                    num += 1;
-                }
-                assert!(num == 0);
+    // the cost of deleting every edge
+    pub fn edge_weight(&self) -> u32 {
+        let mut total = 0u32;
+        for &v1 in &self.active {
+            for v2 in self.positive(v1).filter(|&v2| v1 < v2) {
+                total = total.saturating_add(self[[v1, v2]].weight as u32)
             }
         }
+        total
     }
-}
 
-pub fn finish_solve(output: &mut Graph) {
-    // let out = unsafe { &*(output as *const Graph) };
-    // for (i1, v1) in out.active.all(0) {
-    //     for (i2, v2) in out.positive(v1, i1) {
-    //         let edge_weight = output[[v1, v2]].weight;
-    //         for (_, v3) in out.conflict_edges(v1, v2, i2) {
-    //             let (edge1, edge2) = (output[[v1, v3]], output[[v2, v3]]);
-    //             if edge_weight <= edge1.weight.abs() && edge_weight <= edge2.weight.abs() {
-    //                 output.cut(v1, v2);
-    //             } else if edge1.weight > 0 {
-    //                 if edge1.weight <= -edge2.weight {
-    //                     output.cut(v1, v3);
-    //                 }
-    //             } else if edge2.weight <= -edge1.weight {
-    //                 output.cut(v2, v3);
-    //             }
-    //         }
-    //     }
-    // }
-
-    for mut v1 in output.active.clone() {
-        if output.vertex_merged[v1].is_some() {
-            continue;
-        }
-        for (_, v2) in output.positive(v1, 0).collect::<Vec<_>>() {
-            v1 = output.merge(v1, v2).0;
-            output.vertex_merged[v1] = None
+    // a third vertex in conflict with an edge has an edge to one of its vertices
+    pub fn check_easy(&self) {
+        for &v1 in &self.active {
+            for v2 in self.positive(v1).filter(|&v2| v2 != v1) {
+                let mut thirds = self.positive(v1).chain(self.positive(v2));
+                assert!(!thirds.any(|v3| -self[[v1, v3]].weight ^ -self[[v2, v3]].weight < 0));
+            }
         }
     }
 }
 
-pub fn write_solution<F: Write>(input: &Graph, output: &Graph, file: F) -> io::Result<u32> {
+pub fn write_solution<S: Storage<Edge>, F: Write>(
+    input: &Graph<S>,
+    output: &Graph,
+    file: F,
+) -> io::Result<u32> {
     let mut writer = BufWriter::new(file);
 
-    let mut count = 0;
-    for (i1, v1) in input.active.all(0) {
-        for (_, v2) in input.active.all(i1) {
-            let edge = input[[v1, v2]].weight > 0;
-            if edge != (output.root(v1) == output.root(v2)) {
-                writeln!(&mut writer, "{} {}", v1 + 1, v2 + 1)?;
-                count += 1;
-            }
-        }
+    // in order, like the pairs of the input
+    let mut edits = vec![];
+    let mut cost = 0u32;
+    input.for_each_edit(
+        |v| output.root(v),
+        |v1, v2, edge| {
+            edits.push([v1.min(v2), v1.max(v2)]);
+            cost = cost.saturating_add(edge.weight.unsigned_abs());
+        },
+    );
+    edits.sort_unstable();
+    for [v1, v2] in edits {
+        writeln!(&mut writer, "{} {}", v1 + 1, v2 + 1)?;
     }
-    Ok(count)
+    Ok(cost)
 }
 
-pub fn write<F: Write>(input: &Graph, output: &Graph, file: F) -> io::Result<()> {
+pub fn solution_cost<S: Storage<Edge>>(input: &Graph<S>, output: &Graph) -> u32 {
+    let mut cost = 0u32;
+    input.for_each_edit(
+        |v| output.root(v),
+        |_, _, edge| cost = cost.saturating_add(edge.weight.unsigned_abs()),
+    );
+    cost
+}
+
+pub fn write<S: Storage<Edge>, F: Write>(
+    input: &Graph<S>,
+    output: &Graph,
+    file: F,
+) -> io::Result<()> {
     let mut writer = BufWriter::new(file);
     // writeln!(
     //     &mut writer,
@@ -134,3 +389,10 @@ pub fn write<F: Write>(input: &Graph, output: &Graph, file: F) -> io::Result<()>
     }
     Ok(())
 }
+
+// the clustering of `size` vertices in a file as written by `write`, with a line for every pair in
+// the same cluster. every vertex is labelled with the first vertex in its cluster
+pub fn read_clustering<F: Read>(size: usize, file: F) -> Result<Vec<usize>, Violation> {
+    let joined = verify::read_pairs(size, file)?;
+    verify::clusters(size, |v1, v2| joined.contains(&(v1.min(v2), v1.max(v2))))
+}
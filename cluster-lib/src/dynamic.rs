@@ -0,0 +1,218 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+use crate::{
+    config::{ClusterCount, SolverConfig},
+    decompose::{connected, Component},
+    disk::MAX_WEIGHT,
+    graph::{Edge, Graph},
+    matrix::{Matrix, Storage},
+    search::Solver,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EditError {
+    // the vertex is not an active vertex of the graph
+    OutOfRange { v: usize },
+    SelfLoop { v: usize },
+    // a weight of 0 is neither an edge nor a non-edge
+    ZeroWeight { v1: usize, v2: usize },
+    // like in `disk::load`, the weights beyond `MAX_WEIGHT` are reserved for fixed pairs
+    BadWeight { v1: usize, v2: usize, weight: i32 },
+    // the pair is forbidden or linked, its weight is not up for editing
+    Fixed { v1: usize, v2: usize },
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EditError::OutOfRange { v } => write!(f, "{} is not a vertex of the graph", v + 1),
+            EditError::SelfLoop { v } => write!(f, "{} can not be paired with itself", v + 1),
+            EditError::ZeroWeight { v1, v2 } => {
+                write!(f, "the pair {} {} can not have weight 0", v1 + 1, v2 + 1)
+            }
+            EditError::BadWeight { v1, v2, weight } => write!(
+                f,
+                "the weight {} of the pair {} {} is out of range",
+                weight,
+                v1 + 1,
+                v2 + 1
+            ),
+            EditError::Fixed { v1, v2 } => {
+                write!(f, "the pair {} {} is fixed by a constraint", v1 + 1, v2 + 1)
+            }
+        }
+    }
+}
+
+impl Error for EditError {}
+
+// keeps the optimum of a graph, as loaded, whose edges change a few at a time. an optimal
+// clustering never joins different components of the edges, so every component keeps its own
+// solver, and an update only searches the components it touches again from their previous
+// clustering
+pub struct DynamicSolver<S: Storage<Edge> = Matrix<Edge>> {
+    pub graph: Graph<S>,
+    pub config: SolverConfig,
+    // the optimal clustering of `graph` and its cost
+    pub best: Graph,
+    pub cost: u32,
+    // how many components the last update solved
+    pub resolved: usize,
+    parts: Vec<Part<S>>,
+    // the part of every active vertex and its index in there
+    place: Vec<(usize, usize)>,
+}
+
+// a component with the solver that keeps its optimum
+struct Part<S: Storage<Edge>> {
+    vertices: Vec<usize>,
+    solver: Solver<S>,
+}
+
+impl<S: Storage<Edge>> DynamicSolver<S> {
+    pub fn new(graph: Graph<S>, config: SolverConfig) -> Self {
+        let mut solver = Self {
+            best: Graph::record(&graph),
+            place: vec![(usize::MAX, 0); graph.vertex_merged.len()],
+            graph,
+            config,
+            cost: 0,
+            resolved: 0,
+            parts: vec![],
+        };
+        let active = solver.graph.active.clone();
+        solver.resolved = solver.rebuild(&active);
+        solver.sum_costs();
+        solver
+    }
+
+    // set the weights of pairs of vertices, positive for an edge, and solve the graph again.
+    // returns the new optimal cost. nothing is changed when an edit is invalid
+    pub fn update(&mut self, edits: &[(usize, usize, i32)]) -> Result<u32, EditError> {
+        for &(v1, v2, weight) in edits {
+            for v in [v1, v2] {
+                if !self.graph.is_active.get(v).copied().unwrap_or(false) {
+                    return Err(EditError::OutOfRange { v });
+                }
+            }
+            if v1 == v2 {
+                return Err(EditError::SelfLoop { v: v1 });
+            }
+            if weight == 0 {
+                return Err(EditError::ZeroWeight { v1, v2 });
+            }
+            if weight.unsigned_abs() > MAX_WEIGHT as u32 {
+                return Err(EditError::BadWeight { v1, v2, weight });
+            }
+            if self.graph[[v1, v2]].fixed {
+                return Err(EditError::Fixed { v1, v2 });
+            }
+        }
+        for &(v1, v2, weight) in edits {
+            self.graph[[v1, v2]] = Edge::new(weight);
+        }
+
+        // an edit touches the part that has both vertices, or both parts when it joins them
+        let mut touched = vec![];
+        for &(v1, v2, weight) in edits {
+            let (p1, p2) = (self.place[v1].0, self.place[v2].0);
+            if p1 == p2 || weight > 0 {
+                touched.extend([p1, p2]);
+            }
+        }
+        touched.sort_unstable();
+        touched.dedup();
+        let vertices: Vec<_> = (touched.iter())
+            .flat_map(|&p| self.parts[p].vertices.iter().copied())
+            .collect();
+        let replaced: Vec<_> = (touched.iter().copied())
+            .filter(|&p| !self.edit_part(p, edits))
+            .collect();
+        self.resolved = touched.len() - replaced.len();
+
+        // the parts whose vertices changed are solved from scratch, the last ones first so the
+        // others keep their place
+        let vertices: Vec<_> = (vertices.into_iter())
+            .filter(|&v| replaced.binary_search(&self.place[v].0).is_ok())
+            .collect();
+        for &p in replaced.iter().rev() {
+            self.parts.swap_remove(p);
+            if let Some(moved) = self.parts.get(p) {
+                for (i, &v) in moved.vertices.iter().enumerate() {
+                    self.place[v] = (p, i);
+                }
+            }
+        }
+        self.resolved += self.rebuild(&vertices);
+        self.sum_costs();
+        Ok(self.cost)
+    }
+
+    // apply the edits of the part to its solver and search again, when the part keeps its
+    // vertices. returns whether it does
+    fn edit_part(&mut self, p: usize, edits: &[(usize, usize, i32)]) -> bool {
+        let part = &self.parts[p];
+        if self.config.cluster_count == ClusterCount::Any {
+            let component = connected(&self.graph, &part.vertices[..1]).remove(0);
+            let same = component.len() == part.vertices.len();
+            if !same || component.iter().any(|&v| self.place[v].0 != p) {
+                return false;
+            }
+        }
+        let place = &self.place;
+        let local: Vec<_> = (edits.iter())
+            .filter(|&&(v1, v2, _)| place[v1].0 == p && place[v2].0 == p)
+            .map(|&(v1, v2, weight)| (place[v1].1, place[v2].1, weight))
+            .collect();
+        let part = &mut self.parts[p];
+        part.solver.edit(&local);
+        part.solver.solve();
+        for (i, &v) in part.vertices.iter().enumerate() {
+            let first = part.vertices[part.solver.best.root(i)];
+            self.best.vertex_merged[v] = (first != v).then_some(first);
+        }
+        true
+    }
+
+    // make parts for the components of `vertices`, each one starting from the previous
+    // clustering. returns how many there are
+    fn rebuild(&mut self, vertices: &[usize]) -> usize {
+        let components = if self.config.cluster_count == ClusterCount::Any {
+            connected(&self.graph, vertices)
+        } else {
+            // the count is shared by all components
+            vec![vertices.to_vec()]
+        };
+        let count = components.len();
+        for vertices in components {
+            let component = Component::new(&self.graph, vertices);
+            let previous: Vec<_> = (component.vertices.iter())
+                .map(|&v| self.best.root(v))
+                .collect();
+            let mut solver = Solver::new(component.graph, self.config);
+            // for deletion an edit can take away an edge the previous clustering joins, then it
+            // is no start
+            let _ = solver.start_from(&previous);
+            solver.solve();
+            for (i, &v) in component.vertices.iter().enumerate() {
+                let first = component.vertices[solver.best.root(i)];
+                self.best.vertex_merged[v] = (first != v).then_some(first);
+                self.place[v] = (self.parts.len(), i);
+            }
+            self.parts.push(Part {
+                vertices: component.vertices,
+                solver,
+            });
+        }
+        count
+    }
+
+    fn sum_costs(&mut self) {
+        // a component without a solution leaves the whole graph without one
+        self.cost =
+            (self.parts.iter()).fold(0, |cost: u32, part| cost.saturating_add(part.solver.upper));
+    }
+}
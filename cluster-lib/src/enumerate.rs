@@ -0,0 +1,144 @@
+use crate::{
+    branch::EdgeMod,
+    config::ProblemVariant,
+    graph::{AllFrom, Edge, Graph},
+    matrix::Storage,
+    search::{Solver, Step},
+};
+
+// every branch that can still reach the optimal cost is searched, so each leaf is an optimal
+// clustering and no two leaves are the same. components are not split while enumerating
+impl<S: Storage<Edge>> Solver<S> {
+    // report every optimal clustering until `report` returns false, returns how many were reported
+    // and whether that is all of them, which it is not when `report` or the deadline stopped it.
+    // the clusterings are like `best`, with every vertex pointing to the first one in its cluster
+    pub fn enumerate<F: FnMut(&Graph) -> bool>(&mut self, mut report: F) -> (u64, bool) {
+        if !self.optimal && !self.infeasible {
+            self.solve();
+        }
+        if self.infeasible {
+            return (0, true);
+        }
+        if !self.optimal {
+            return (0, false);
+        }
+        if self.config.variant == ProblemVariant::Completion {
+            report(&self.best);
+            return (1, true);
+        }
+        if !self.config.incremental {
+            self.packing.pack(&self.graph); // the previous search leaves a stale packing behind
+        }
+        let mut count = 0;
+        let complete = self.enumerate_graph(&mut report, &mut count);
+        (count, complete)
+    }
+
+    // like `enumerate`, the count is only a lower bound when it is not complete
+    pub fn count_optimal(&mut self) -> (u64, bool) {
+        self.enumerate(|_| true)
+    }
+
+    // returns false once the search has to stop
+    fn enumerate_graph<F: FnMut(&Graph) -> bool>(
+        &mut self,
+        report: &mut F,
+        count: &mut u64,
+    ) -> bool {
+        // the steps are kept on a stack instead of recursing. once the search has to stop only
+        // the steps that take merges and cuts back are left to do
+        let mut stack = vec![Step::Graph];
+        let mut more = true;
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::UnMerge { v1, v2, vv, cost } => {
+                    self.upper += cost;
+                    self.un_merge(v1, v2, vv);
+                }
+                Step::UnCut { v1, v2, edge, cost } => {
+                    self.upper += cost;
+                    self.un_cut(v1, v2, edge);
+                }
+                _ if !more => {}
+                Step::Graph => more = self.enumerate_step(report, count, &mut stack),
+                Step::Merge(v1, v2) => {
+                    if !self.fits_size(v1, v2) {
+                        continue;
+                    }
+                    let (vv, cost) = self.merge(v1, v2);
+                    if self.packing.lower.saturating_add(cost) <= self.upper {
+                        self.upper -= cost;
+                        stack.push(Step::UnMerge { v1, v2, vv, cost });
+                        stack.push(Step::Graph);
+                    } else {
+                        self.un_merge(v1, v2, vv);
+                    }
+                }
+                Step::Cut(v1, v2) => {
+                    let (edge, cost) = self.cut(v1, v2);
+                    if self.packing.lower.saturating_add(cost) <= self.upper {
+                        self.upper -= cost;
+                        stack.push(Step::UnCut { v1, v2, edge, cost });
+                        stack.push(Step::Graph);
+                    } else {
+                        self.un_cut(v1, v2, edge);
+                    }
+                }
+                // components are not split while enumerating
+                Step::Components | Step::FirstComponent { .. } | Step::OtherComponents { .. } => {
+                    unreachable!()
+                }
+            }
+        }
+        more
+    }
+
+    // branch or report a leaf, returns false once the search has to stop
+    fn enumerate_step<F: FnMut(&Graph) -> bool>(
+        &mut self,
+        report: &mut F,
+        count: &mut u64,
+        stack: &mut Vec<Step>,
+    ) -> bool {
+        if self.should_stop() {
+            return false;
+        }
+        match self.fit_lower() {
+            Some(lower) if self.packing.lower.saturating_add(lower) <= self.upper => {}
+            _ => return true,
+        }
+        self.stats.nodes += 1;
+        // without conflicts the clusters can still be joined along pairs of weight 0 for free
+        let edge = match self.best_edge() {
+            EdgeMod::Nothing => match self.zero_pair() {
+                EdgeMod::Nothing => self.fit_edge(),
+                edge => edge,
+            },
+            edge => edge,
+        };
+        match edge {
+            EdgeMod::Merge(v1, v2) | EdgeMod::Cut(v1, v2) => {
+                stack.push(Step::Cut(v1, v2));
+                stack.push(Step::Merge(v1, v2));
+                true
+            }
+            EdgeMod::Nothing => {
+                let clusters = self.leaf_clusters();
+                *count += 1;
+                report(&Graph::from_clusters(&self.graph, &clusters))
+            }
+        }
+    }
+
+    fn zero_pair(&self) -> EdgeMod {
+        for (i1, v1) in self.graph.active.all(0) {
+            for (_, v2) in self.graph.active.all(i1) {
+                let edge = self.graph[[v1, v2]];
+                if !edge.fixed && edge.weight == 0 {
+                    return EdgeMod::Merge(v1, v2);
+                }
+            }
+        }
+        EdgeMod::Nothing
+    }
+}
@@ -1,19 +1,25 @@
 use std::{
+    collections::BTreeMap,
     mem::replace,
     ops::{Deref, DerefMut},
 };
 
-use crate::matrix::Matrix;
+use crate::matrix::{Matrix, Sparse, Storage};
 
+// a graph with its pairs in `S`, a dense matrix unless it is loaded as sparse
 #[derive(Debug)]
-pub struct Graph {
+pub struct Graph<S = Matrix<Edge>> {
     pub vertex_merged: Vec<Option<usize>>,
-    pub edges: Matrix<Edge>,
+    pub edges: S,
     pub active: Vec<usize>,
+    // whether every vertex is in `active`, so the loops over stored pairs can skip the others
+    pub is_active: Vec<bool>,
     pub len: usize,
+    // how many original vertices each vertex stands for
+    pub size: Vec<usize>,
 }
 
-impl PartialEq for Graph {
+impl<S: Storage<Edge>> PartialEq for Graph<S> {
     fn eq(&self, other: &Self) -> bool {
         let mut self_clusters = self.active.clone();
         let mut other_clusters = other.active.clone();
@@ -33,19 +39,26 @@ impl PartialEq for Graph {
     }
 }
 
-impl Clone for Graph {
+impl<S: Storage<Edge>> Clone for Graph<S> {
     fn clone(&self) -> Self {
         Self {
             vertex_merged: self.vertex_merged.clone(),
             edges: self.edges.clone(),
             active: self.active.clone(),
+            is_active: self.is_active.clone(),
             len: self.len,
+            size: self.size.clone(),
         }
     }
 
     fn clone_from(&mut self, source: &Self) {
+        if self.edges.base().is_some() {
+            *self = source.clone();
+            return;
+        }
         self.active.clear();
         self.active.extend_from_slice(&source.active);
+        self.is_active.copy_from_slice(&source.is_active);
         for v in 0..self.vertex_merged.len() {
             if source.vertex_merged[v].is_some() {
                 self.vertex_merged[v] = source.vertex_merged[v];
@@ -58,6 +71,7 @@ impl Clone for Graph {
             }
         }
         self.len = source.len;
+        self.size.copy_from_slice(&source.size);
     }
 }
 
@@ -81,22 +95,199 @@ impl Edge {
             fixed: true,
         }
     }
+
+    // the edge between two merged vertices standing for `count` pairs of this edge
+    pub fn scale(self, count: usize) -> Self {
+        if self.fixed {
+            self
+        } else {
+            Self::new(saturate(self.weight as i64 * count as i64))
+        }
+    }
+}
+
+// a weight that stays off the extremes reserved for fixed pairs, like `disk::MAX_WEIGHT` keeps the
+// weights of a loaded graph
+pub fn saturate(weight: i64) -> i32 {
+    let max = i32::MAX as i64 - 1;
+    weight.clamp(-max, max) as i32
 }
 
 impl Graph {
     pub fn new(size: usize) -> Self {
+        Self::filled(size, -1)
+    }
+
+    // a graph that only records which vertices of `graph` are merged, so `root` is the same in both
+    pub fn record<S: Storage<Edge>>(graph: &Graph<S>) -> Self {
+        Self {
+            vertex_merged: graph.vertex_merged.clone(),
+            edges: Matrix::new(Edge::none(), 0),
+            active: vec![],
+            is_active: vec![],
+            len: 0,
+            size: vec![],
+        }
+    }
+
+    // a graph that only records a clustering, `clusters` labels the vertices of `graph`
+    pub fn from_clusters<S: Storage<Edge>>(graph: &Graph<S>, clusters: &[usize]) -> Self {
+        let mut output = Self {
+            vertex_merged: vec![None; graph.vertex_merged.len()],
+            edges: Matrix::new(Edge::none(), 0),
+            active: vec![],
+            is_active: vec![],
+            len: 0,
+            size: vec![],
+        };
+        output.store_clusters(graph, clusters);
+        output
+    }
+}
+
+impl Graph<Sparse<Edge>> {
+    // a graph whose memory grows with the edges instead of the square of the size
+    pub fn sparse(size: usize, weight: i32) -> Self {
+        Self::filled(size, weight)
+    }
+}
+
+impl<S: Storage<Edge>> Graph<S> {
+    pub fn filled(size: usize, weight: i32) -> Self {
+        let mut is_active = vec![false; size * 2];
+        is_active[..size].fill(true);
         Self {
             vertex_merged: vec![None; size * 2],
-            edges: Matrix::new(Edge::new(-1), size * 2),
+            edges: S::new(Edge::new(weight), size * 2),
             active: (0..size).collect(),
+            is_active,
             len: size,
+            size: vec![1; size * 2],
         }
     }
 
-    pub fn positive(&self, v1: usize, from: usize) -> impl '_ + Iterator<Item = (usize, usize)> {
-        self.active
-            .all(from)
-            .filter(move |&(_, v2)| self[[v1, v2]].weight > 0)
+    pub fn activate(&mut self, v: usize) {
+        self.active.push(v);
+        self.is_active[v] = true;
+    }
+
+    pub fn deactivate(&mut self, v: usize) {
+        self.active.retain(|&v2| v2 != v);
+        self.is_active[v] = false;
+    }
+
+    // point every original vertex to the first original vertex in its cluster, so `root` gives
+    // the cluster. vertices that are merged into a vertex labelled `usize::MAX` are skipped
+    pub fn store_clusters<S2: Storage<Edge>>(&mut self, graph: &Graph<S2>, clusters: &[usize]) {
+        let mut first = vec![None; clusters.len()];
+        for v in 0..graph.vertex_merged.len() / 2 {
+            let cluster = clusters[graph.root(v)];
+            if cluster == usize::MAX {
+                continue;
+            }
+            let first = *first[cluster].get_or_insert(v);
+            self.vertex_merged[v] = if first == v { None } else { Some(first) };
+        }
+    }
+
+    // whether the pairs without a value of their own are no edges, then the edges of a vertex are
+    // found through its stored pairs
+    pub fn sparse_edges(&self) -> bool {
+        matches!(self.edges.base(), Some(base) if base.weight <= 0)
+    }
+
+    // the active vertices with an edge to `v1`, `v1` included when it is active
+    pub fn positive(&self, v1: usize) -> impl '_ + Iterator<Item = usize> {
+        let (stored, all) = if self.sparse_edges() {
+            (Some(self.edges.neighbours(v1)), None)
+        } else {
+            (None, Some(self.active.iter().copied()))
+        };
+        let stored = stored
+            .into_iter()
+            .flatten()
+            .filter(move |&v2| self.is_active[v2]);
+        stored
+            .chain(all.into_iter().flatten())
+            .filter(move |&v2| self[[v1, v2]].weight > 0)
+    }
+
+    // how many active vertices stand for each number of original vertices
+    pub fn active_sizes(&self) -> BTreeMap<usize, usize> {
+        let mut sizes = BTreeMap::new();
+        for &v in &self.active {
+            *sizes.entry(self.size[v]).or_insert(0) += 1;
+        }
+        sizes
+    }
+
+    // the edges of `v1` to the other active vertices it has no stored pair with, with how many
+    // have each edge. `sizes` are the `active_sizes`. there are none when every pair is stored
+    pub fn default_edges(&self, v1: usize, sizes: &BTreeMap<usize, usize>) -> Vec<(Edge, usize)> {
+        if self.edges.base().is_none() {
+            return vec![];
+        }
+        let mut counts = sizes.clone();
+        let others = self.edges.neighbours(v1).filter(|&v2| v2 != v1);
+        for v2 in others.chain(Some(v1)).filter(|&v2| self.is_active[v2]) {
+            *counts.get_mut(&self.size[v2]).unwrap() -= 1;
+        }
+        (counts.into_iter())
+            .filter(|&(_, count)| count > 0)
+            .map(|(size, count)| (self.edges.default_with(v1, size).unwrap(), count))
+            .collect()
+    }
+
+    // the edges of `v1` to the other active vertices with how many have each one, the stored pairs
+    // one at a time and then the `default_edges`
+    pub fn edges_of<'a>(
+        &'a self,
+        v1: usize,
+        sizes: &BTreeMap<usize, usize>,
+    ) -> impl 'a + Iterator<Item = (Edge, usize)> {
+        let stored = (self.edges.neighbours(v1)).filter(move |&v2| v2 != v1 && self.is_active[v2]);
+        (stored.map(move |v2| (self[[v1, v2]], 1))).chain(self.default_edges(v1, sizes))
+    }
+
+    // every pair of active vertices that a clustering edits, `cluster` gives the cluster of an
+    // active vertex. on sparse edges the deletions are found through the stored pairs and the
+    // insertions inside the clusters, so not every pair is looked at
+    pub fn for_each_edit<C: Fn(usize) -> usize, F: FnMut(usize, usize, Edge)>(
+        &self,
+        cluster: C,
+        mut edit: F,
+    ) {
+        if !self.sparse_edges() {
+            for (i1, v1) in self.active.all(0) {
+                for (_, v2) in self.active.all(i1) {
+                    let edge = self[[v1, v2]];
+                    if (edge.weight > 0) != (cluster(v1) == cluster(v2)) {
+                        edit(v1, v2, edge);
+                    }
+                }
+            }
+            return;
+        }
+        for &v1 in &self.active {
+            for v2 in self.edges.neighbours(v1) {
+                let edge = self[[v1, v2]];
+                if v1 < v2 && self.is_active[v2] && edge.weight > 0 && cluster(v1) != cluster(v2) {
+                    edit(v1, v2, edge);
+                }
+            }
+        }
+        let mut members: Vec<_> = self.active.iter().map(|&v| (cluster(v), v)).collect();
+        members.sort_unstable();
+        for members in members.chunk_by(|(c1, _), (c2, _)| c1 == c2) {
+            for (i1, &(_, v1)) in members.iter().enumerate() {
+                for &(_, v2) in &members[i1 + 1..] {
+                    let edge = self[[v1, v2]];
+                    if edge.weight <= 0 {
+                        edit(v1, v2, edge);
+                    }
+                }
+            }
+        }
     }
 
     pub fn cut(&mut self, v1: usize, v2: usize) -> Edge {
@@ -107,24 +298,23 @@ impl Graph {
         self[[v1, v2]] = edge;
     }
 
-    pub fn root(&self, index: usize) -> usize {
-        if let Some(new_index) = self.vertex_merged[index] {
-            self.root(new_index)
-        } else {
-            index
+    pub fn root(&self, mut index: usize) -> usize {
+        while let Some(new_index) = self.vertex_merged[index] {
+            index = new_index;
         }
+        index
     }
 }
 
-impl Deref for Graph {
-    type Target = Matrix<Edge>;
+impl<S> Deref for Graph<S> {
+    type Target = S;
 
     fn deref(&self) -> &Self::Target {
         &self.edges
     }
 }
 
-impl DerefMut for Graph {
+impl<S> DerefMut for Graph<S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.edges
     }
@@ -0,0 +1,229 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rand::{prelude::SliceRandom, rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    graph::{AllFrom, Edge, Graph},
+    matrix::Storage,
+};
+
+// clusterings label every vertex id with the id of a cluster, inactive vertices get `usize::MAX`
+
+// best of `rounds` pivot clusterings, each improved by local search
+pub fn heuristic<S: Storage<Edge>>(graph: &Graph<S>, rounds: usize) -> (Vec<usize>, u64) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut best = (vec![], u64::MAX);
+    let never = AtomicBool::new(false);
+    for _ in 0..rounds {
+        let mut clusters = pivot(graph, &mut rng);
+        local_search(graph, &mut clusters, &never);
+        let cost = clustering_cost(graph, &clusters);
+        if cost < best.1 {
+            best = (clusters, cost);
+        }
+    }
+    best
+}
+
+// perturb the best clustering and search locally again until `stop` is set,
+// every improvement is reported with its cost. the first report can come before the local search
+// is done, when `stop` is set during it
+pub fn improve<S: Storage<Edge>, F: FnMut(&[usize], u64)>(
+    graph: &Graph<S>,
+    seed: u64,
+    stop: &AtomicBool,
+    mut report: F,
+) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut best = pivot(graph, &mut rng);
+    local_search(graph, &mut best, stop);
+    let mut best_cost = clustering_cost(graph, &best);
+    report(&best, best_cost);
+
+    let mut clusters = best.clone();
+    while !stop.load(Ordering::Relaxed) {
+        clusters.copy_from_slice(&best);
+        perturb(graph, &mut clusters, &mut rng);
+        local_search(graph, &mut clusters, stop);
+        let cost = clustering_cost(graph, &clusters);
+        if cost < best_cost {
+            best.copy_from_slice(&clusters);
+            best_cost = cost;
+            report(&best, best_cost);
+        }
+    }
+}
+
+// move a few random vertices to the cluster of a random neighbour
+fn perturb<S: Storage<Edge>, R: Rng>(graph: &Graph<S>, clusters: &mut [usize], rng: &mut R) {
+    let count = rng.gen_range(1..=1 + graph.active.len() / 50);
+    for _ in 0..count {
+        let v1 = match graph.active.choose(rng) {
+            Some(&v1) => v1,
+            None => return,
+        };
+        let neighbours: Vec<_> = graph.positive(v1).filter(|&v2| v2 != v1).collect();
+        if let Some(&v2) = neighbours.choose(rng) {
+            clusters[v1] = clusters[v2];
+        }
+    }
+}
+
+// pick random unclustered vertices and cluster them with their unclustered neighbours
+pub fn pivot<S: Storage<Edge>, R: Rng>(graph: &Graph<S>, rng: &mut R) -> Vec<usize> {
+    let mut clusters = vec![usize::MAX; graph.vertex_merged.len()];
+    let mut order = graph.active.clone();
+    order.shuffle(rng);
+    for v1 in order {
+        if clusters[v1] != usize::MAX {
+            continue;
+        }
+        clusters[v1] = v1;
+        for v2 in graph.positive(v1) {
+            if clusters[v2] == usize::MAX {
+                clusters[v2] = v1;
+            }
+        }
+    }
+    clusters
+}
+
+// move vertices to the cluster that lowers the cost the most until no move helps or `stop` is set
+pub fn local_search<S: Storage<Edge>>(graph: &Graph<S>, clusters: &mut [usize], stop: &AtomicBool) {
+    let len = clusters.len();
+    // on sparse edges only the clusters of the stored pairs are summed up, the other vertices of a
+    // cluster add the default through `mass`, `unit` of them for every vertex
+    let sparse = graph.sparse_edges();
+    let base = graph.edges.base().unwrap_or_else(|| Edge::new(0));
+    let unit = |v: usize| if base.fixed { 1 } else { graph.size[v] as i64 };
+    let mut size = vec![0; len];
+    let mut mass = vec![0i64; len];
+    for &v in &graph.active {
+        size[clusters[v]] += 1;
+        mass[clusters[v]] += unit(v);
+    }
+    let mut empty: Vec<_> = (0..len).filter(|&c| size[c] == 0).collect();
+
+    // total weight from the current vertex to every cluster
+    let mut gain = vec![0i64; len];
+    let mut seen = vec![false; len];
+    let mut touched = vec![];
+
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for &v1 in &graph.active {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let current = clusters[v1];
+            // the weight of `v1` to one unit of a vertex without a stored pair
+            let default = match (sparse, base.fixed) {
+                (false, _) => 0,
+                (true, true) => base.weight as i64,
+                (true, false) => base.weight as i64 * graph.size[v1] as i64,
+            };
+            seen[current] = true;
+            touched.push(current);
+            let (stored, all) = if sparse {
+                (Some(graph.edges.neighbours(v1)), None)
+            } else {
+                (None, Some(graph.active.iter().copied()))
+            };
+            let stored = stored
+                .into_iter()
+                .flatten()
+                .filter(|&v2| graph.is_active[v2]);
+            for v2 in stored.chain(all.into_iter().flatten()) {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                if v1 != v2 {
+                    let c = clusters[v2];
+                    if !seen[c] {
+                        seen[c] = true;
+                        touched.push(c);
+                    }
+                    gain[c] += graph[[v1, v2]].weight as i64 - default * unit(v2);
+                }
+            }
+            for &c in &touched {
+                let own = if c == current { unit(v1) } else { 0 };
+                gain[c] += default * (mass[c] - own);
+            }
+
+            let (mut best_gain, mut best) = (0, None);
+            for &c in &touched {
+                if c != current && gain[c] > best_gain {
+                    best_gain = gain[c];
+                    best = Some(c);
+                }
+            }
+            // no best cluster means moving to a new one
+            if best_gain > gain[current] {
+                let target = best.unwrap_or_else(|| empty.pop().unwrap());
+                size[current] -= 1;
+                mass[current] -= unit(v1);
+                if size[current] == 0 {
+                    empty.push(current);
+                }
+                size[target] += 1;
+                mass[target] += unit(v1);
+                clusters[v1] = target;
+                improved = true;
+            }
+
+            for c in touched.drain(..) {
+                gain[c] = 0;
+                seen[c] = false;
+            }
+        }
+    }
+}
+
+// the connected components of the edges, the only clustering without deletions
+pub fn closure<S: Storage<Edge>>(graph: &Graph<S>) -> Vec<usize> {
+    let mut clusters = vec![usize::MAX; graph.vertex_merged.len()];
+    for (_, v1) in graph.active.all(0) {
+        if clusters[v1] != usize::MAX {
+            continue;
+        }
+        clusters[v1] = v1;
+        let mut stack = vec![v1];
+        while let Some(v2) = stack.pop() {
+            for v3 in graph.positive(v2) {
+                if clusters[v3] == usize::MAX {
+                    clusters[v3] = v1;
+                    stack.push(v3);
+                }
+            }
+        }
+    }
+    clusters
+}
+
+pub fn clustering_cost<S: Storage<Edge>>(graph: &Graph<S>, clusters: &[usize]) -> u64 {
+    let mut cost = 0;
+    graph.for_each_edit(
+        |v| clusters[v],
+        |_, _, edge| cost += edge.weight.unsigned_abs() as u64,
+    );
+    cost
+}
+
+// the cost of the clustering as an upper bound of the search, `u32::MAX` when it joins a fixed
+// pair or costs that much
+pub fn upper_bound<S: Storage<Edge>>(graph: &Graph<S>, clusters: &[usize]) -> u32 {
+    let (mut cost, mut joins_fixed) = (0u64, false);
+    graph.for_each_edit(
+        |v| clusters[v],
+        |_, _, edge| {
+            cost += edge.weight.unsigned_abs() as u64;
+            joins_fixed |= edge.fixed;
+        },
+    );
+    if joins_fixed {
+        return u32::MAX;
+    }
+    cost.min(u32::MAX as u64) as u32
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::{ClusterCount, ProblemVariant, SolverConfig},
+    graph::{AllFrom, Edge, Graph},
+    matrix::{Matrix, Storage},
+};
+
+// the sums of the heavy rules for every vertex, merged vertices change the sums of their
+// neighbours, so they wait for the next round
+struct Sums {
+    positive: Vec<i64>,
+    absolute: Vec<i64>,
+    merged: Vec<bool>,
+}
+
+pub struct Kernel<S: Storage<Edge> = Matrix<Edge>> {
+    pub graph: Graph<S>,
+    // the cost of the modifications made by the reduction rules
+    pub cost: u32,
+}
+
+impl<S: Storage<Edge>> Kernel<S> {
+    // reduce the graph, solutions of `Kernel::graph` can be lifted to solutions of `graph`
+    pub fn new(graph: Graph<S>) -> Self {
+        let mut kernel = Self { graph, cost: 0 };
+        if kernel.unweighted() {
+            kernel.critical_cliques();
+        }
+        while kernel.heavy_rules() {}
+        kernel.isolated_cliques();
+        kernel
+    }
+
+    // the rules are safe for deletion once the non-edges are forbidden. completion forbids no
+    // pairs, so the heavy non-edge rule could make it infeasible. the rules do not keep the
+    // number or size of the clusters, so bounds on them skip them too
+    pub fn for_config(mut graph: Graph<S>, config: &SolverConfig) -> Self {
+        if config.cluster_count != ClusterCount::Any || config.max_cluster_size.is_some() {
+            return Self { graph, cost: 0 };
+        }
+        match config.variant {
+            ProblemVariant::Editing => Self::new(graph),
+            ProblemVariant::Deletion => {
+                graph.restrict(config.variant);
+                Self::new(graph)
+            }
+            ProblemVariant::Completion => Self { graph, cost: 0 },
+        }
+    }
+
+    // whether a clustering of the original vertices keeps the merged vertices together and the
+    // forbidden pairs apart, so it is a start for the reduced graph too
+    pub fn fits(&self, clusters: &[usize], variant: ProblemVariant) -> bool {
+        self.graph.root_labels(clusters, variant).is_ok()
+    }
+
+    // the clustering of the original vertices, given the best clustering of the reduced graph
+    pub fn lift(&self, best: &Graph) -> Graph {
+        let size = self.graph.vertex_merged.len() / 2;
+        let mut clusters = vec![usize::MAX; self.graph.vertex_merged.len()];
+        for v in 0..size {
+            clusters[self.graph.root(v)] = best.root(v);
+        }
+        Graph::from_clusters(&self.graph, &clusters)
+    }
+
+    fn unweighted(&self) -> bool {
+        let plain = self.plain();
+        self.graph.active.iter().all(|&v| plain[v])
+    }
+
+    fn merge_all(&mut self, vertices: &[usize]) -> usize {
+        let mut vv = vertices[0];
+        for &v in &vertices[1..] {
+            let (merged, cost) = self.graph.merge(vv, v);
+            vv = merged;
+            self.cost = self.cost.saturating_add(cost);
+        }
+        vv
+    }
+
+    // vertices with the same closed neighbourhood, a critical clique K, end up in the same cluster.
+    // the rules below decide that the closed neighbourhood N[K] is contained in one cluster or
+    // is a cluster of its own. they are applied one clique at a time to what the previous ones
+    // left, but only where all pairs of N[K] still have weight 1 or -1
+    fn critical_cliques(&mut self) {
+        let mut mark = vec![0u8; self.graph.vertex_merged.len()];
+        while let Some((closed, isolated)) = self.clique_rule(&mut mark) {
+            let vv = self.merge_all(&closed);
+            if isolated {
+                self.graph.deactivate(vv);
+                for v in self.graph.positive(vv) {
+                    let weight = self.graph[[vv, v]].weight as u32;
+                    self.cost = self.cost.saturating_add(weight);
+                }
+            }
+        }
+        // the vertices of a plain clique have the same weight to every other vertex
+        let plain = self.plain();
+        for (_, clique) in self.cliques() {
+            if plain[clique[0]] {
+                self.merge_all(&clique);
+            }
+        }
+    }
+
+    // whether every pair of the vertex has weight 1 or -1, merged vertices never do
+    fn plain(&self) -> Vec<bool> {
+        let graph = &self.graph;
+        let sizes = graph.active_sizes();
+        let mut plain = vec![false; graph.vertex_merged.len()];
+        for &v1 in &graph.active {
+            plain[v1] =
+                (graph.edges_of(v1, &sizes)).all(|(edge, _)| !edge.fixed && edge.weight.abs() == 1);
+        }
+        plain
+    }
+
+    // the critical cliques with their closed neighbourhoods, in the order of `active`
+    fn cliques(&self) -> Vec<(Vec<usize>, Vec<usize>)> {
+        let graph = &self.graph;
+        let mut cliques = vec![];
+        let mut index = HashMap::new();
+        for &v1 in &graph.active {
+            let mut neighbourhood: Vec<_> = graph.positive(v1).collect();
+            neighbourhood.push(v1);
+            neighbourhood.sort_unstable();
+            neighbourhood.dedup();
+            let i = *index.entry(neighbourhood.clone()).or_insert_with(|| {
+                cliques.push((neighbourhood, vec![]));
+                cliques.len() - 1
+            });
+            cliques[i].1.push(v1);
+        }
+        cliques
+    }
+
+    // the closed neighbourhood of the first critical clique K that a rule applies to, and whether
+    // it is a cluster of its own. it is when |K| >= |N(K)| + |N2(K)| (Guo), and it is contained in
+    // one cluster when 2 p(N[K]) + c(N[K]) < |N[K]|, with p the non-edges inside N[K] and c the
+    // edges leaving it (the 2k kernel of Cao and Chen). `mark` is 0 for all vertices and stays so
+    fn clique_rule(&self, mark: &mut [u8]) -> Option<(Vec<usize>, bool)> {
+        let graph = &self.graph;
+        let plain = self.plain();
+        for (neighbourhood, clique) in self.cliques() {
+            if neighbourhood.len() == 1 || !neighbourhood.iter().all(|&v| plain[v]) {
+                continue;
+            }
+            // 1 for the clique, 2 for its neighbours and 3 for their other neighbours
+            for &v in &neighbourhood {
+                mark[v] = 2;
+            }
+            for &v in &clique {
+                mark[v] = 1;
+            }
+            let neighbours: Vec<_> = neighbourhood
+                .iter()
+                .copied()
+                .filter(|&v| mark[v] == 2)
+                .collect();
+            let mut second = vec![];
+            let mut leaving = 0;
+            for &v1 in &neighbours {
+                for v2 in graph.positive(v1) {
+                    if mark[v2] == 0 {
+                        mark[v2] = 3;
+                        second.push(v2);
+                    }
+                    leaving += (mark[v2] == 3) as usize;
+                }
+            }
+            let mut missing = 0;
+            for (i, &v1) in neighbours.iter().enumerate() {
+                for &v2 in &neighbours[i + 1..] {
+                    missing += (graph[[v1, v2]].weight < 0) as usize;
+                }
+            }
+            for &v in neighbourhood.iter().chain(&second) {
+                mark[v] = 0;
+            }
+
+            if clique.len() >= neighbours.len() + second.len() {
+                return Some((neighbourhood, true));
+            }
+            if !neighbours.is_empty() && 2 * missing + leaving < neighbourhood.len() {
+                return Some((neighbourhood, false));
+            }
+        }
+        None
+    }
+
+    // the heavy non-edge and heavy edge rules for weighted graphs, returns whether anything changed
+    fn heavy_rules(&mut self) -> bool {
+        let len = self.graph.vertex_merged.len();
+        let sizes = self.graph.active_sizes();
+        let mut sums = Sums {
+            positive: vec![0i64; len],
+            absolute: vec![0i64; len],
+            merged: vec![false; len],
+        };
+        for &v1 in &self.graph.active {
+            for (edge, count) in self.graph.edges_of(v1, &sizes) {
+                let weight = edge.weight as i64 * count as i64;
+                sums.positive[v1] += weight.max(0);
+                sums.absolute[v1] += weight.abs();
+            }
+        }
+
+        let mut changed = false;
+        let active = self.graph.active.clone();
+        if self.graph.sparse_edges() {
+            // the pairs without a value of their own are no edges and stay free, so only the
+            // stored pairs can be merged or forbidden
+            let mut pairs = vec![];
+            for &v1 in &active {
+                let stored = self.graph.edges.neighbours(v1);
+                pairs.extend(
+                    stored
+                        .filter(|&v2| v1 < v2 && self.graph.is_active[v2])
+                        .map(|v2| (v1, v2)),
+                );
+            }
+            for (v1, v2) in pairs {
+                changed |= self.heavy_pair(v1, v2, &mut sums);
+            }
+            return changed;
+        }
+        for (i1, v1) in active.all(0) {
+            for (_, v2) in active.all(i1) {
+                changed |= self.heavy_pair(v1, v2, &mut sums);
+            }
+        }
+        changed
+    }
+
+    // apply the heavy rules to a pair, returns whether it was merged or forbidden
+    fn heavy_pair(&mut self, v1: usize, v2: usize, sums: &mut Sums) -> bool {
+        let Sums {
+            positive,
+            absolute,
+            merged,
+        } = sums;
+        if merged[v1] || merged[v2] {
+            return false;
+        }
+        let edge = self.graph[[v1, v2]];
+        let weight = edge.weight as i64;
+        if weight > 0 {
+            let single = weight >= absolute[v1] - weight || weight >= absolute[v2] - weight;
+            let both = weight >= positive[v1] - weight + positive[v2] - weight;
+            if single || both {
+                merged[v1] = true;
+                merged[v2] = true;
+                self.merge_all(&[v1, v2]);
+                return true;
+            }
+        } else if !edge.fixed
+            && self.graph.edges.stored([v1, v2]) // sparse graphs keep default pairs free
+            && (-weight >= positive[v1] || -weight >= positive[v2])
+        {
+            self.graph[[v1, v2]] = Edge::none();
+            absolute[v1] += i32::MAX as i64 + weight;
+            absolute[v2] += i32::MAX as i64 + weight;
+            return true;
+        }
+        false
+    }
+
+    // components that are already cliques are clusters of their own
+    fn isolated_cliques(&mut self) {
+        let len = self.graph.vertex_merged.len();
+        let mut visited = vec![false; len];
+        for v1 in self.graph.active.clone() {
+            if visited[v1] {
+                continue;
+            }
+            visited[v1] = true;
+            let mut members = vec![v1];
+            let mut next = 0;
+            while let Some(&v2) = members.get(next) {
+                next += 1;
+                for v3 in self.graph.positive(v2) {
+                    if !visited[v3] {
+                        visited[v3] = true;
+                        members.push(v3);
+                    }
+                }
+            }
+            let clique = members.iter().enumerate().all(|(i, &v2)| {
+                members[i + 1..]
+                    .iter()
+                    .all(|&v3| self.graph[[v2, v3]].weight > 0)
+            });
+            if clique {
+                let vv = self.merge_all(&members);
+                self.graph.deactivate(vv);
+            }
+        }
+    }
+}
@@ -1,29 +1,179 @@
-mod branch;
+pub mod branch;
 mod component;
+pub mod config;
+pub mod constraint;
+mod count;
+pub mod decompose;
 pub mod disk;
+pub mod dynamic;
+mod enumerate;
 pub mod graph;
-mod matrix;
+pub mod heuristic;
+pub mod kernel;
+pub mod lp;
+pub mod matrix;
 mod merge;
 mod packing;
+mod queue;
 pub mod search;
+pub mod stats;
 mod triple;
+pub mod verify;
 
 #[cfg(test)]
 mod tests {
     use std::{
+        cmp::max,
         fs::File,
         io::{self, Write},
-        process::Command,
-        time::Instant,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
     };
 
-    use rand::{prelude::SliceRandom, thread_rng};
+    use rand::{prelude::SliceRandom, rngs::StdRng, thread_rng, Rng, SeedableRng};
 
     use crate::{
-        disk::{load, write, write_solution},
+        branch::{BranchingRule, EdgeMod},
+        config::{
+            Branching, ClusterCount, ConflictCost, Improvement, LpBound, ProblemVariant,
+            SolverConfig,
+        },
+        constraint::Constraints,
+        decompose::{solve_components, solve_components_from, split},
+        disk::{
+            load, load_constraints, load_sparse, read_clustering, solution_cost, write,
+            write_solution, ParseError, MAX_WEIGHT,
+        },
+        dynamic::{DynamicSolver, EditError},
+        graph::{Edge, Graph},
+        heuristic::{clustering_cost, improve, local_search},
+        kernel::Kernel,
+        lp::lp_lower,
+        matrix::Storage,
+        packing::Packing,
         search::Solver,
+        verify::{verify, Violation},
     };
 
+    fn random_instance(size: usize, seed: u64) -> String {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut edges = Vec::new();
+        for v1 in 1..=size {
+            for v2 in v1 + 1..=size {
+                if rng.gen_bool(0.5) {
+                    edges.push(format!("{} {}\n", v1, v2));
+                }
+            }
+        }
+        format!("p cep {} {}\n{}", size, edges.len(), edges.concat())
+    }
+
+    fn random_weighted_instance(size: usize, seed: u64) -> String {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut pairs = Vec::new();
+        for v1 in 1..=size {
+            for v2 in v1 + 1..=size {
+                let weight = rng.gen_range(1..=4) * if rng.gen_bool(0.4) { 1 } else { -1 };
+                pairs.push(format!("{} {} {}\n", v1, v2, weight));
+            }
+        }
+        format!("p wcep {} {}\n{}", size, pairs.len(), pairs.concat())
+    }
+
+    // unweighted on even seeds and weighted on odd ones
+    fn seeded_instance(size: usize, seed: u64) -> String {
+        if seed % 2 == 1 {
+            random_weighted_instance(size, seed)
+        } else {
+            random_instance(size, seed)
+        }
+    }
+
+    // the optimal cost of a partition of at most 5 vertices, in a u64 for costs that do not fit a u32
+    fn brute_force_wide<S: Storage<Edge>>(graph: &Graph<S>) -> u64 {
+        let size = graph.active.len();
+        let mut best = u64::MAX;
+        // every vertex picks the cluster of a vertex up to itself, which covers every partition
+        for code in 0..size.pow(size as u32) {
+            let clusters: Vec<_> = (0..size)
+                .map(|v| code / size.pow(v as u32) % size)
+                .collect();
+            if (0..size).any(|v| clusters[v] > v) {
+                continue;
+            }
+            let root = |mut v: usize| {
+                while clusters[v] != v {
+                    v = clusters[v];
+                }
+                v
+            };
+            let roots: Vec<_> = (0..size).map(root).collect();
+            best = best.min(clustering_cost(graph, &roots));
+        }
+        best
+    }
+
+    fn brute_force<S: Storage<Edge>>(graph: &Graph<S>) -> u32 {
+        brute_force_count(graph).0
+    }
+
+    // try every partition of the vertices, returns the optimal cost and how many partitions have it
+    fn brute_force_count<S: Storage<Edge>>(graph: &Graph<S>) -> (u32, u64) {
+        brute_force_fitting(graph, ClusterCount::Any, usize::MAX)
+    }
+
+    // like `brute_force_count`, only for the partitions that fit the number and size of clusters
+    fn brute_force_fitting<S: Storage<Edge>>(
+        graph: &Graph<S>,
+        count: ClusterCount,
+        size: usize,
+    ) -> (u32, u64) {
+        fn partition<S: Storage<Edge>>(
+            graph: &Graph<S>,
+            bounds: (ClusterCount, usize),
+            clusters: &mut Vec<usize>,
+            best: &mut (u32, u64),
+        ) {
+            let (count, size) = bounds;
+            if clusters.len() == graph.active.len() {
+                let used = clusters.iter().max().map_or(0, |&c| c + 1);
+                let largest = (0..used).map(|c| clusters.iter().filter(|&&v| v == c).count());
+                if !count.fits(used) || largest.max().unwrap_or(0) > size {
+                    return;
+                }
+                let mut cost = 0;
+                for v1 in 0..clusters.len() {
+                    for v2 in v1 + 1..clusters.len() {
+                        let weight = graph[[v1, v2]].weight;
+                        if (weight > 0) != (clusters[v1] == clusters[v2]) {
+                            cost += weight.unsigned_abs();
+                        }
+                    }
+                }
+                if cost < best.0 {
+                    *best = (cost, 0);
+                }
+                if cost == best.0 {
+                    best.1 += 1;
+                }
+                return;
+            }
+            let used = clusters.iter().max().map_or(0, |&c| c + 1);
+            for cluster in 0..=used {
+                clusters.push(cluster);
+                partition(graph, bounds, clusters, best);
+                clusters.pop();
+            }
+        }
+        let mut best = (u32::MAX, 0);
+        partition(graph, (count, size), &mut vec![], &mut best);
+        best
+    }
+
     #[test]
     fn test() {
         // let instances = vec![
@@ -46,18 +196,14 @@ mod tests {
             let mut graph = load(File::open(&file_name).unwrap()).unwrap();
             graph.active.shuffle(&mut thread_rng());
             // critical(&mut graph);
-            let mut solver = Solver::new(graph);
-
-            if cfg!(feature = "perfect-upper") {
-                solver.upper = sol
-            }
+            let mut solver = Solver::new(graph, SolverConfig::default());
 
             solver.search_components();
             print!("({}, {})", instance, time.elapsed().as_millis());
             io::stdout().flush().unwrap();
             assert_eq!(solver.upper, sol);
 
-            if cfg!(any(feature = "perfect-upper", feature = "branch-comp")) {
+            if solver.config.branch_components {
                 continue;
             }
 
@@ -78,17 +224,9 @@ mod tests {
             .unwrap();
             assert_eq!(solver.upper, count2);
 
-            assert_eq!(
-                std::str::from_utf8(
-                    &Command::new("../verifier/verifier.exe")
-                        .args(&[file_name, out_file])
-                        .output()
-                        .unwrap()
-                        .stdout
-                )
-                .unwrap(),
-                "OK\r\n"
-            )
+            let input = load(File::open(&file_name).unwrap()).unwrap();
+            let cost = verify(&input, File::open(&out_file).unwrap()).unwrap();
+            assert_eq!(solver.upper, cost);
         }
     }
 
@@ -107,7 +245,7 @@ mod tests {
         for instance in (1..200).step_by(2) {
             let file_name = format!("../exact/exact{:03}.gr", instance);
             let graph = load(File::open(file_name).unwrap()).unwrap();
-            let solver = Solver::new(graph);
+            let solver = Solver::new(graph, SolverConfig::default());
             // critical(&mut graph);
             println!("{:03} {}", instance, solver.packing.lower);
             // let actual = search_components(&mut graph, i32::MAX, &mut Graph::new(0));
@@ -126,7 +264,1461 @@ mod tests {
     fn edge_count() {
         let graph = load(File::open("../exact/exact025.gr").unwrap()).unwrap();
         for vertex in graph.active.clone() {
-            println!("{}", graph.positive(vertex, 0).count());
+            println!("{}", graph.positive(vertex).count());
+        }
+    }
+
+    #[test]
+    fn load_errors() {
+        let graph = load("c comment\np cep 3 2\n1 2\n2 3\n".as_bytes()).unwrap();
+        assert_eq!(graph.edge_count(), 2);
+
+        let err = |input: &str| load(input.as_bytes()).unwrap_err();
+        assert!(matches!(
+            err("p cep 3\n"),
+            ParseError::BadHeader { line: 1, .. }
+        ));
+        assert!(matches!(
+            err("c\np cap 3 1\n"),
+            ParseError::WrongProblem { line: 2, .. }
+        ));
+        assert!(matches!(
+            err("p cep x 1\n"),
+            ParseError::NotANumber { line: 1, .. }
+        ));
+        assert!(matches!(
+            err("p cep 3 1\n1\n"),
+            ParseError::MalformedLine { line: 2, .. }
+        ));
+        assert!(matches!(
+            err("p wcep 3 1\n1 2\n"),
+            ParseError::MalformedLine { line: 2, .. }
+        ));
+        assert!(matches!(
+            err("p wcep 3 1\n1 2 -2147483648\n"),
+            ParseError::BadWeight { line: 2, .. }
+        ));
+        assert!(matches!(
+            err("p cep 3 1\n0 1\n"),
+            ParseError::VertexOutOfRange { line: 2, .. }
+        ));
+        assert!(matches!(
+            err("p cep 3 1\n1 4\n"),
+            ParseError::VertexOutOfRange { line: 2, .. }
+        ));
+        assert!(matches!(
+            err("p cep 3 1\n2 2\n"),
+            ParseError::SelfLoop { line: 2, .. }
+        ));
+        assert!(matches!(
+            err("p cep 3 2\n1 2\n2 1\n"),
+            ParseError::DuplicateEdge { line: 3, .. }
+        ));
+        assert!(matches!(
+            err("p cep 3 2\n1 2\n"),
+            ParseError::EdgeCount {
+                expected: 2,
+                found: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn weighted() {
+        let cases = [
+            ("p wcep 3 2 -3\n1 2 5\n2 3 1\n", 1, ["2", "3"]),
+            ("p wcep 3 3\n1 2 5\n2 3 4\n1 3 -2\n", 2, ["1", "3"]),
+        ];
+        for (input, cost, modified) in cases {
+            let graph = load(input.as_bytes()).unwrap();
+            let mut solver = Solver::new(graph, SolverConfig::default());
+            solver.search_components();
+            assert_eq!(solver.upper, cost);
+
+            let mut out = Vec::new();
+            assert_eq!(
+                write_solution(&solver.graph, &solver.best, &mut out).unwrap(),
+                cost
+            );
+            let mut pair: Vec<_> = std::str::from_utf8(&out)
+                .unwrap()
+                .split_whitespace()
+                .collect();
+            pair.sort_unstable();
+            assert_eq!(pair, modified);
+        }
+    }
+
+    #[test]
+    fn weight_bound() {
+        // only the weights reserved for fixed pairs are out of range, whatever the vertex count
+        let header = |weight: i32| format!("p wcep 5 1\n1 2 {}\n", weight);
+        assert!(load(header(-MAX_WEIGHT).as_bytes()).is_ok());
+        assert!(matches!(
+            load(header(MAX_WEIGHT + 1).as_bytes()),
+            Err(ParseError::BadWeight { line: 2, .. })
+        ));
+        assert!(matches!(
+            load(format!("p wcep 5 0 {}\n", -MAX_WEIGHT - 1).as_bytes()),
+            Err(ParseError::BadWeight { line: 1, .. })
+        ));
+        let graph = load_sparse("p wcep 100000 1 -3\n1 2 1000000\n".as_bytes()).unwrap();
+        assert_eq!(graph[[0, 1]].weight, 1000000);
+
+        // costs close to the largest u32, merged vertices add up the weights of their pairs
+        let bound = MAX_WEIGHT / 8;
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut pairs = Vec::new();
+            for v1 in 1..=5 {
+                for v2 in v1 + 1..=5 {
+                    let weight = bound - rng.gen_range(0..3);
+                    let weight = if rng.gen_bool(0.5) { weight } else { -weight };
+                    pairs.push(format!("{} {} {}\n", v1, v2, weight));
+                }
+            }
+            let input = format!("p wcep 5 {}\n{}", pairs.len(), pairs.concat());
+            let graph = load(input.as_bytes()).unwrap();
+            let optimal = brute_force(&graph);
+
+            let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+            assert_eq!(solver.upper, solution_cost(&graph, &solver.best));
+            solver.solve();
+            assert_eq!(solver.upper, optimal, "seed {}", seed);
+            assert_eq!(solution_cost(&graph, &solver.best), optimal);
+
+            let kernel = Kernel::new(graph.clone());
+            let (best, cost, _) = solve_components(&kernel.graph, SolverConfig::default());
+            assert_eq!(kernel.cost + cost, optimal, "seed {}", seed);
+            assert_eq!(solution_cost(&graph, &kernel.lift(&best)), optimal);
+        }
+
+        // every pair at the largest weight, the costs saturate instead of wrapping around. only the
+        // optimal clusterings that cost less than `u32::MAX` are found
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut pairs = Vec::new();
+            for v1 in 1..=5 {
+                for v2 in v1 + 1..=5 {
+                    let weight = if rng.gen_bool(0.5) {
+                        MAX_WEIGHT
+                    } else {
+                        -MAX_WEIGHT
+                    };
+                    pairs.push(format!("{} {} {}\n", v1, v2, weight));
+                }
+            }
+            let input = format!("p wcep 5 {}\n{}", pairs.len(), pairs.concat());
+            let graph = load(input.as_bytes()).unwrap();
+            let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+            solver.solve();
+            if solver.upper == u32::MAX {
+                assert!(brute_force_wide(&graph) >= u32::MAX as u64);
+                continue;
+            }
+            assert_eq!(
+                solver.upper as u64,
+                brute_force_wide(&graph),
+                "seed {}",
+                seed
+            );
+            assert_eq!(solution_cost(&graph, &solver.best), solver.upper);
+            let mut file = vec![];
+            write_solution(&graph, &solver.best, &mut file).unwrap();
+            assert_eq!(verify(&graph, &file[..]).unwrap(), solver.upper);
+        }
+    }
+
+    #[test]
+    fn configs() {
+        let mut configs = Vec::new();
+        for conflict_cost in [
+            ConflictCost::Count,
+            ConflictCost::MinWeight,
+            ConflictCost::Deletion,
+        ] {
+            for incremental in [false, true] {
+                for lower_bound in [false, true] {
+                    for branch_components in [false, true] {
+                        for heuristic_rounds in [0, 10] {
+                            for deepening in [false, true] {
+                                for threads in [1, 3] {
+                                    configs.push(SolverConfig {
+                                        variant: ProblemVariant::Editing,
+                                        conflict_cost,
+                                        incremental,
+                                        lower_bound,
+                                        lp_bound: LpBound::Off,
+                                        lp_pairs: 250,
+                                        lp_entries: 1 << 18,
+                                        lp_pivots: 2000,
+                                        improve_packing: Improvement::Off,
+                                        branching: Branching::MostConflicts,
+                                        branch_components,
+                                        heuristic_rounds,
+                                        deepening,
+                                        threads,
+                                        cluster_count: ClusterCount::Any,
+                                        max_cluster_size: None,
+                                        time_packing: threads > 1,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for seed in 0..20 {
+            let graph = load(random_instance(7, seed).as_bytes()).unwrap();
+            let optimal = brute_force(&graph);
+            for &config in &configs {
+                let mut solver = Solver::new(graph.clone(), config);
+                let initial = solver.upper;
+                solver.solve();
+                assert!(solver.optimal);
+                assert_eq!(solver.upper, optimal, "{:?} seed {}", config, seed);
+                assert_eq!(solution_cost(&graph, &solver.best), optimal);
+
+                let stats = &solver.stats;
+                assert!(stats.root_lower <= optimal);
+                assert!(config.time_packing || stats.packing_time.is_zero());
+                let mut last = initial;
+                for &(_, upper) in &stats.improvements {
+                    assert!(upper < last, "{:?} seed {}", config, seed);
+                    last = upper;
+                }
+                assert_eq!(last, optimal, "{:?} seed {}", config, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn components() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut edges = Vec::new();
+            for v1 in 1..=9 {
+                for v2 in v1 + 1..=9 {
+                    if rng.gen_bool(0.2) {
+                        edges.push(format!("{} {}\n", v1, v2));
+                    }
+                }
+            }
+            let input = format!("p cep 9 {}\n{}", edges.len(), edges.concat());
+            let graph = load(input.as_bytes()).unwrap();
+            let optimal = brute_force(&graph);
+
+            let count: usize = split(&graph).iter().map(|c| c.vertices.len()).sum();
+            assert_eq!(count, 9);
+            for threads in [1, 3] {
+                let config = SolverConfig {
+                    threads,
+                    ..SolverConfig::default()
+                };
+                let (best, cost, _) = solve_components(&graph, config);
+                assert_eq!(cost, optimal);
+                assert_eq!(solution_cost(&graph, &best), optimal);
+
+                let kernel = Kernel::new(graph.clone());
+                let (best, cost, _) = solve_components(&kernel.graph, config);
+                assert_eq!(kernel.cost + cost, optimal);
+                assert_eq!(solution_cost(&graph, &kernel.lift(&best)), optimal);
+            }
+        }
+    }
+
+    #[test]
+    fn enumerate() {
+        for seed in 0..20 {
+            let input = if seed % 2 == 0 {
+                random_instance(7, seed)
+            } else {
+                random_weighted_instance(7, seed)
+            };
+            let graph = load(input.as_bytes()).unwrap();
+            let (optimal, count) = brute_force_count(&graph);
+            let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+
+            let mut clusterings = Vec::new();
+            let reported = solver.enumerate(|best| {
+                assert_eq!(solution_cost(&graph, best), optimal);
+                clusterings.push((0..7).map(|v| best.root(v)).collect::<Vec<_>>());
+                true
+            });
+            assert_eq!(reported, (count, true), "seed {}", seed);
+            clusterings.sort();
+            clusterings.dedup();
+            assert_eq!(clusterings.len() as u64, count);
+
+            assert_eq!(solver.enumerate(|_| false), (1, false));
+            assert_eq!(solver.count_optimal(), (count, true));
+            assert_eq!(solver.upper, optimal);
+
+            solver.cancel = Some(Arc::new(AtomicBool::new(true)));
+            assert_eq!(solver.count_optimal(), (0, false));
+        }
+    }
+
+    #[test]
+    fn constraints() {
+        let graph = load("p cep 3 2\n1 2\n2 3\n".as_bytes()).unwrap();
+        let constraints = load_constraints(&graph, "c path\nmust 1 3\ncannot 2 3\n".as_bytes());
+        let constraints = constraints.unwrap();
+        assert_eq!(constraints.must_link, vec![(0, 2)]);
+        assert_eq!(constraints.cannot_link, vec![(1, 2)]);
+        assert!(load_constraints(&graph, "must 1 4\n".as_bytes()).is_err());
+        assert!(load_constraints(&graph, "may 1 2\n".as_bytes()).is_err());
+        let contradiction = Constraints {
+            must_link: vec![(0, 1), (1, 2)],
+            cannot_link: vec![(0, 2)],
+        };
+        assert!(graph.clone().constrain(&contradiction).is_err());
+
+        for seed in 0..30 {
+            let input = if seed % 2 == 0 {
+                random_instance(7, seed)
+            } else {
+                random_weighted_instance(7, seed)
+            };
+            let graph = load(input.as_bytes()).unwrap();
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut pairs: Vec<_> = (0..7)
+                .flat_map(|v1| (v1 + 1..7).map(move |v2| (v1, v2)))
+                .collect();
+            pairs.shuffle(&mut rng);
+            let constraints = Constraints {
+                must_link: pairs[..2].to_vec(),
+                cannot_link: pairs[2..4].to_vec(),
+            };
+
+            // forcing the pairs with large weights gives the same optimum
+            let mut forced = graph.clone();
+            let mut forced_cost = 0;
+            for &(v1, v2) in &constraints.must_link {
+                forced_cost += max(0, -forced[[v1, v2]].weight) as u32;
+                forced[[v1, v2]].weight = 1000;
+            }
+            for &(v1, v2) in &constraints.cannot_link {
+                forced_cost += max(0, forced[[v1, v2]].weight) as u32;
+                forced[[v1, v2]].weight = -1000;
+            }
+            let optimal = brute_force(&forced);
+
+            let mut constrained = graph.clone();
+            let cost = match constrained.constrain(&constraints) {
+                Ok(cost) => cost,
+                Err(_) => {
+                    assert!(optimal >= 1000);
+                    continue;
+                }
+            };
+            let kernel = Kernel::new(constrained);
+            let mut solver = Solver::new(kernel.graph.clone(), SolverConfig::default());
+            solver.solve();
+            assert_eq!(
+                cost + kernel.cost + solver.upper,
+                optimal + forced_cost,
+                "seed {}",
+                seed
+            );
+
+            let lifted = kernel.lift(&solver.best);
+            for &(v1, v2) in &constraints.must_link {
+                assert_eq!(lifted.root(v1), lifted.root(v2));
+            }
+            for &(v1, v2) in &constraints.cannot_link {
+                assert_ne!(lifted.root(v1), lifted.root(v2));
+            }
+            let mut out = Vec::new();
+            write_solution(&graph, &lifted, &mut out).unwrap();
+            assert_eq!(verify(&graph, &out[..]).unwrap(), optimal + forced_cost);
+        }
+    }
+
+    #[test]
+    fn cluster_count() {
+        for seed in 0..20 {
+            let input = if seed % 2 == 0 {
+                random_instance(7, seed)
+            } else {
+                random_weighted_instance(7, seed)
+            };
+            let graph = load(input.as_bytes()).unwrap();
+            for p in 1..=7 {
+                for count in [ClusterCount::AtMost(p), ClusterCount::Exactly(p)] {
+                    let (optimal, number) = brute_force_fitting(&graph, count, usize::MAX);
+                    for threads in [1, 3] {
+                        let config = SolverConfig {
+                            cluster_count: count,
+                            threads,
+                            ..SolverConfig::default()
+                        };
+                        let (best, cost, _) = solve_components(&graph, config);
+                        assert_eq!(cost, optimal, "seed {} {:?}", seed, count);
+                        assert_eq!(solution_cost(&graph, &best), optimal);
+                        let clusters = (0..7).filter(|&v| best.root(v) == v).count();
+                        assert!(count.fits(clusters));
+                    }
+                    let config = SolverConfig {
+                        cluster_count: count,
+                        ..SolverConfig::default()
+                    };
+                    assert_eq!(
+                        Solver::new(graph.clone(), config).count_optimal(),
+                        (number, true)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_cluster_size() {
+        for seed in 0..20 {
+            let input = if seed % 2 == 0 {
+                random_instance(7, seed)
+            } else {
+                random_weighted_instance(7, seed)
+            };
+            let graph = load(input.as_bytes()).unwrap();
+            for size in 1..=4 {
+                let (optimal, number) = brute_force_fitting(&graph, ClusterCount::Any, size);
+                for (incremental, branch_components) in [(true, false), (false, true)] {
+                    let config = SolverConfig {
+                        max_cluster_size: Some(size),
+                        incremental,
+                        branch_components,
+                        ..SolverConfig::default()
+                    };
+                    let mut solver = Solver::new(graph.clone(), config);
+                    solver.solve();
+                    assert_eq!(solver.upper, optimal, "seed {} size {}", seed, size);
+                    for v1 in 0..7 {
+                        let members = (0..7).filter(|&v2| solver.best.root(v2) == v1).count();
+                        assert!(members <= size);
+                    }
+                    let (_, cost, _) = solve_components(&graph, config);
+                    assert_eq!(cost, optimal);
+                    assert_eq!(
+                        Solver::new(graph.clone(), config).count_optimal(),
+                        (number, true)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn initial_solution() {
+        for seed in 0..20 {
+            let input = seeded_instance(8, seed);
+            let graph = load(input.as_bytes()).unwrap();
+            let optimal = brute_force(&graph);
+            let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+            solver.solve();
+
+            // the pairs of the optimal clustering make the start
+            let mut out = Vec::new();
+            write(&graph, &solver.best, &mut out).unwrap();
+            let clusters = read_clustering(8, &out[..]).unwrap();
+            let config = SolverConfig {
+                heuristic_rounds: 0,
+                ..SolverConfig::default()
+            };
+            let warm = Solver::new(graph.clone(), config).with_initial_solution(&clusters);
+            let mut warm = warm.unwrap();
+            assert_eq!(warm.upper, optimal);
+            warm.solve();
+            assert!(warm.optimal);
+            assert_eq!(warm.upper, optimal);
+            assert_eq!(solution_cost(&graph, &warm.best), optimal);
+        }
+
+        // the kernel and the components keep the start on larger graphs
+        for seed in 0..6 {
+            let graph = load(seeded_instance(16, seed).as_bytes()).unwrap();
+            let (best, optimal, _) = solve_components(&graph, SolverConfig::default());
+            let mut out = Vec::new();
+            write(&graph, &best, &mut out).unwrap();
+            let clusters = read_clustering(16, &out[..]).unwrap();
+            let (best, cost, _) =
+                solve_components_from(&graph, SolverConfig::default(), &clusters).unwrap();
+            assert_eq!(cost, optimal);
+            assert_eq!(solution_cost(&graph, &best), optimal);
+
+            let kernel = Kernel::new(graph.clone());
+            assert!(kernel.fits(&clusters, ProblemVariant::Editing));
+            let solver = Solver::new(kernel.graph.clone(), SolverConfig::default());
+            let mut solver = solver.with_initial_solution(&clusters).unwrap();
+            assert!(kernel.cost + solver.upper >= optimal);
+            solver.solve();
+            assert_eq!(kernel.cost + solver.upper, optimal, "seed {}", seed);
+        }
+
+        // the kernel merges each triangle, a start that splits one does not fit it
+        let graph = load("p cep 6 6\n1 2\n2 3\n1 3\n4 5\n5 6\n4 6\n".as_bytes()).unwrap();
+        let kernel = Kernel::new(graph.clone());
+        assert!(kernel.fits(&[0, 0, 0, 1, 1, 1], ProblemVariant::Editing));
+        assert!(!kernel.fits(&[0, 0, 1, 1, 1, 1], ProblemVariant::Editing));
+        let (best, cost, _) =
+            solve_components_from(&graph, SolverConfig::default(), &[0, 0, 1, 2, 2, 2]).unwrap();
+        assert_eq!(cost, 0);
+        assert_eq!(solution_cost(&graph, &best), 0);
+
+        let graph = load("p cep 3 2\n1 2\n2 3\n".as_bytes()).unwrap();
+        assert!(matches!(
+            read_clustering(3, "1 2\n2 3\n".as_bytes()),
+            Err(Violation::NotClique { .. })
+        ));
+        let solver = || Solver::new(graph.clone(), SolverConfig::default());
+        assert!(matches!(
+            solver().with_initial_solution(&[0, 0]),
+            Err(Violation::WrongLength { .. })
+        ));
+        let mut constrained = graph.clone();
+        let constraints = Constraints {
+            must_link: vec![(0, 1)],
+            cannot_link: vec![(1, 2)],
+        };
+        constrained.constrain(&constraints).unwrap();
+        let solver = || Solver::new(constrained.clone(), SolverConfig::default());
+        assert!(matches!(
+            solver().with_initial_solution(&[0, 1, 1]),
+            Err(Violation::Separated { v1: 1, v2: 2 })
+        ));
+        assert!(matches!(
+            solver().with_initial_solution(&[0, 0, 0]),
+            Err(Violation::Joined { .. })
+        ));
+        assert_eq!(solver().with_initial_solution(&[5, 5, 7]).unwrap().upper, 0);
+        let config = SolverConfig {
+            cluster_count: ClusterCount::Exactly(1),
+            ..SolverConfig::default()
+        };
+        let solver = Solver::new(graph.clone(), config);
+        assert!(matches!(
+            solver.with_initial_solution(&[0, 0, 1]),
+            Err(Violation::Bounds)
+        ));
+    }
+
+    #[test]
+    fn lp_bound() {
+        for seed in 0..20 {
+            let input = seeded_instance(8, seed);
+            let graph = load(input.as_bytes()).unwrap();
+            let optimal = brute_force(&graph);
+            let packing = Solver::new(graph.clone(), SolverConfig::default())
+                .packing
+                .lower;
+            let lower = lp_lower(&graph, &SolverConfig::default());
+            assert!(packing <= lower && lower <= optimal, "seed {}", seed);
+
+            for lp_bound in [LpBound::Root, LpBound::Nodes] {
+                for (deepening, threads, branch_components) in [
+                    (false, 1, false),
+                    (true, 1, false),
+                    (false, 3, false),
+                    (false, 1, true),
+                ] {
+                    let config = SolverConfig {
+                        lp_bound,
+                        deepening,
+                        threads,
+                        branch_components,
+                        ..SolverConfig::default()
+                    };
+                    let mut solver = Solver::new(graph.clone(), config);
+                    assert!(solver.stats.root_lower >= lower);
+                    solver.solve();
+                    assert!(solver.optimal);
+                    assert_eq!(solver.upper, optimal, "{:?} seed {}", config, seed);
+                    assert_eq!(solution_cost(&graph, &solver.best), optimal);
+                }
+            }
+        }
+
+        // the conflict triples of a 5-cycle all share pairs, so the packing only takes two of
+        // them while the relaxation takes half of each
+        let graph = load("p cep 5 5\n1 2\n2 3\n3 4\n4 5\n1 5\n".as_bytes()).unwrap();
+        let solver = Solver::new(graph.clone(), SolverConfig::default());
+        assert!(solver.packing.lower < lp_lower(&graph, &SolverConfig::default()));
+
+        // the relaxation of a larger graph stays within its pivots, and one with too many pairs gets
+        // no bound at all
+        for seed in 0..2 {
+            let graph = load(seeded_instance(22, seed).as_bytes()).unwrap();
+            let start = Instant::now();
+            let lower = lp_lower(&graph, &SolverConfig::default());
+            assert!(start.elapsed() < Duration::from_secs(2));
+            let (_, optimal, _) = solve_components(&graph, SolverConfig::default());
+            assert!(lower <= optimal, "seed {}", seed);
+
+            // the nodes start from the relaxation above them
+            let graph = load(seeded_instance(16, seed).as_bytes()).unwrap();
+            let (_, optimal, _) = solve_components(&graph, SolverConfig::default());
+            let packing = Solver::new(graph.clone(), SolverConfig::default()).packing;
+            let lower = lp_lower(&graph, &SolverConfig::default());
+            assert!(packing.lower <= lower && lower <= optimal, "seed {}", seed);
+            let config = SolverConfig {
+                lp_bound: LpBound::Nodes,
+                ..SolverConfig::default()
+            };
+            let mut solver = Solver::new(graph.clone(), config);
+            solver.solve();
+            assert_eq!(solver.upper, optimal);
+            assert_eq!(solution_cost(&graph, &solver.best), optimal);
+        }
+        let graph = load(random_instance(40, 0).as_bytes()).unwrap();
+        let start = Instant::now();
+        assert_eq!(lp_lower(&graph, &SolverConfig::default()), 0);
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // only the free pairs of conflict triples get a variable, so a path among many isolated
+        // vertices has a bound, unless the limit is below its three pairs
+        let graph = load("p cep 40 2\n1 2\n2 3\n".as_bytes()).unwrap();
+        assert_eq!(lp_lower(&graph, &SolverConfig::default()), 1);
+        let config = SolverConfig {
+            lp_pairs: 2,
+            ..SolverConfig::default()
+        };
+        assert_eq!(lp_lower(&graph, &config), 0);
+    }
+
+    #[test]
+    fn improve_packing() {
+        // the triples account for the whole bound and fit in the weights of the active pairs
+        fn check_triples(graph: &Graph, packing: &Packing) {
+            let size = graph.vertex_merged.len();
+            let mut edge_cost = vec![vec![0; size]; size];
+            for triple in &packing.triples {
+                assert!(triple.vertices.iter().all(|&v| graph.is_active[v]));
+                let [v1, v2, v3] = triple.vertices;
+                for [v1, v2] in [[v1, v2], [v1, v3], [v2, v3]] {
+                    edge_cost[v1][v2] += triple.cost;
+                    edge_cost[v2][v1] += triple.cost;
+                }
+            }
+            let total: u32 = packing.triples.iter().map(|triple| triple.cost).sum();
+            assert_eq!(total, packing.lower);
+            for (i1, &v1) in graph.active.iter().enumerate() {
+                for &v2 in &graph.active[i1 + 1..] {
+                    assert_eq!(edge_cost[v1][v2], packing.edge_cost[[v1, v2]]);
+                    assert!(edge_cost[v1][v2] <= graph[[v1, v2]].weight.unsigned_abs());
+                }
+            }
+        }
+
+        let mut improved = 0;
+        for seed in 0..40 {
+            let input = seeded_instance(8, seed);
+            let mut graph = load(input.as_bytes()).unwrap();
+            graph.active.shuffle(&mut StdRng::seed_from_u64(seed));
+            let optimal = brute_force(&graph);
+            let greedy = Solver::new(graph.clone(), SolverConfig::default()).packing;
+            let config = SolverConfig {
+                improve_packing: Improvement::Root,
+                ..SolverConfig::default()
+            };
+            let packing = Solver::new(graph.clone(), config).packing;
+            assert!(greedy.lower <= packing.lower && packing.lower <= optimal);
+            improved += (greedy.lower < packing.lower) as u32;
+
+            check_triples(&graph, &packing);
+
+            for improve_packing in [
+                Improvement::Root,
+                Improvement::Every(1),
+                Improvement::Every(3),
+            ] {
+                for (incremental, branch_components, deepening, threads) in [
+                    (true, false, false, 1),
+                    (false, false, false, 1),
+                    (true, true, false, 1),
+                    (false, true, false, 1),
+                    (true, false, true, 1),
+                    (true, false, false, 3),
+                ] {
+                    let config = SolverConfig {
+                        improve_packing,
+                        incremental,
+                        branch_components,
+                        deepening,
+                        threads,
+                        ..SolverConfig::default()
+                    };
+                    let mut solver = Solver::new(graph.clone(), config);
+                    solver.solve();
+                    assert_eq!(solver.upper, optimal, "{:?} seed {}", config, seed);
+                    assert_eq!(solution_cost(&graph, &solver.best), optimal);
+                }
+            }
+        }
+        assert!(improved > 0);
+
+        // merges, cuts and taking them back keep the accounting of an improved packing, as it is
+        // improved at every node
+        for seed in 0..10 {
+            let graph = load(seeded_instance(12, seed).as_bytes()).unwrap();
+            let config = SolverConfig {
+                improve_packing: Improvement::Every(1),
+                ..SolverConfig::default()
+            };
+            let mut solver = Solver::new(graph, config);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut changes = vec![];
+            for _ in 0..6 {
+                let [v1, v2] = match solver.best_edge() {
+                    EdgeMod::Merge(v1, v2) | EdgeMod::Cut(v1, v2) => [v1, v2],
+                    EdgeMod::Nothing => break,
+                };
+                if rng.gen() {
+                    let (vv, _) = solver.merge(v1, v2);
+                    changes.push((v1, v2, Err(vv)));
+                } else {
+                    let (edge, _) = solver.cut(v1, v2);
+                    changes.push((v1, v2, Ok(edge)));
+                }
+                check_triples(&solver.graph, &solver.packing);
+                solver.packing.improve(&solver.graph);
+                check_triples(&solver.graph, &solver.packing);
+            }
+            while let Some((v1, v2, change)) = changes.pop() {
+                match change {
+                    Ok(edge) => solver.un_cut(v1, v2, edge),
+                    Err(vv) => solver.un_merge(v1, v2, vv),
+                }
+                check_triples(&solver.graph, &solver.packing);
+                solver.packing.improve(&solver.graph);
+                check_triples(&solver.graph, &solver.packing);
+            }
+        }
+    }
+
+    #[test]
+    fn branching_rules() {
+        // branch on the first pair in a conflict triple
+        #[derive(Clone)]
+        struct FirstConflict;
+        impl BranchingRule for FirstConflict {
+            fn choose(&mut self, solver: &Solver) -> EdgeMod {
+                let graph = &solver.graph;
+                for (i1, &v1) in graph.active.iter().enumerate() {
+                    for &v2 in &graph.active[i1 + 1..] {
+                        if !graph[[v1, v2]].fixed && solver.packing.edge_conflicts[[v1, v2]] > 0 {
+                            return EdgeMod::Merge(v1, v2);
+                        }
+                    }
+                }
+                EdgeMod::Nothing
+            }
+
+            fn box_clone(&self) -> Box<dyn BranchingRule> {
+                Box::new(FirstConflict)
+            }
+        }
+
+        // the pairs the rule of the solver picks in a row
+        fn choices(solver: &mut Solver, count: usize) -> Vec<(bool, usize, usize)> {
+            (0..count)
+                .map(|_| match solver.best_edge() {
+                    EdgeMod::Merge(v1, v2) => (true, v1, v2),
+                    EdgeMod::Cut(v1, v2) => (false, v1, v2),
+                    EdgeMod::Nothing => (false, 0, 0),
+                })
+                .collect()
+        }
+
+        for seed in 0..20 {
+            let input = seeded_instance(8, seed);
+            let graph = load(input.as_bytes()).unwrap();
+            let (optimal, count) = brute_force_count(&graph);
+            for branching in [
+                Branching::MostConflicts,
+                Branching::Vertex,
+                Branching::LowerBound,
+                Branching::Random(seed),
+            ] {
+                for (incremental, threads) in [(true, 1), (false, 1), (true, 3)] {
+                    let config = SolverConfig {
+                        branching,
+                        incremental,
+                        threads,
+                        heuristic_rounds: 0,
+                        ..SolverConfig::default()
+                    };
+                    let mut solver = Solver::new(graph.clone(), config);
+                    solver.solve();
+                    assert_eq!(solver.upper, optimal, "{:?} seed {}", config, seed);
+                    assert_eq!(solution_cost(&graph, &solver.best), optimal);
+                    if threads == 1 {
+                        assert_eq!(solver.count_optimal(), (count, true));
+                    }
+                }
+            }
+
+            let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+            solver.rule = Box::new(FirstConflict);
+            solver.solve();
+            assert_eq!(solver.upper, optimal);
+            assert_eq!(solver.count_optimal(), (count, true));
+        }
+
+        // larger instances against the default rule, with the random one on several threads
+        for seed in 0..6 {
+            let graph = load(seeded_instance(16, seed).as_bytes()).unwrap();
+            let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+            solver.solve();
+            let optimal = solver.upper;
+            for branching in [
+                Branching::Vertex,
+                Branching::LowerBound,
+                Branching::Random(seed),
+            ] {
+                for threads in [1, 3] {
+                    let config = SolverConfig {
+                        branching,
+                        threads,
+                        ..SolverConfig::default()
+                    };
+                    let mut solver = Solver::new(graph.clone(), config);
+                    solver.solve();
+                    assert_eq!(solver.upper, optimal, "{:?} seed {}", config, seed);
+                    assert_eq!(solution_cost(&graph, &solver.best), optimal);
+                }
+            }
+
+            // a clone draws the same ties as the solver it was cloned from, whatever the solver
+            // draws after that
+            let config = SolverConfig {
+                branching: Branching::Random(seed),
+                ..SolverConfig::default()
+            };
+            let mut solver = Solver::new(graph.clone(), config);
+            let mut clone = solver.clone();
+            let drawn = choices(&mut solver, 20);
+            assert_eq!(choices(&mut solver.clone(), 20), choices(&mut solver, 20));
+            assert_eq!(choices(&mut clone, 20), drawn);
+        }
+    }
+
+    #[test]
+    fn bucket_queue() {
+        // the most conflicts over all free pairs
+        fn most_conflicts(solver: &Solver) -> u32 {
+            let graph = &solver.graph;
+            let mut most = 0;
+            for (i1, &v1) in graph.active.iter().enumerate() {
+                for &v2 in &graph.active[i1 + 1..] {
+                    if !graph[[v1, v2]].fixed {
+                        most = max(most, solver.packing.edge_conflicts[[v1, v2]]);
+                    }
+                }
+            }
+            most
+        }
+
+        for seed in 0..20 {
+            for incremental in [true, false] {
+                let graph = load(random_instance(12, seed).as_bytes()).unwrap();
+                let config = SolverConfig {
+                    incremental,
+                    ..SolverConfig::default()
+                };
+                let mut solver = Solver::new(graph, config);
+                let mut rng = StdRng::seed_from_u64(seed);
+                // merge or cut random pairs and take the changes back in reverse
+                let mut changes = vec![];
+                for _ in 0..6 {
+                    let [v1, v2] = match solver.best_edge() {
+                        EdgeMod::Merge(v1, v2) | EdgeMod::Cut(v1, v2) => {
+                            // the pairs of merged vertices leave the queue, also when it is packed
+                            // from scratch
+                            assert!(solver.graph.is_active[v1] && solver.graph.is_active[v2]);
+                            assert!(!solver.graph[[v1, v2]].fixed);
+                            assert_eq!(
+                                solver.packing.edge_conflicts[[v1, v2]],
+                                most_conflicts(&solver)
+                            );
+                            [v1, v2]
+                        }
+                        EdgeMod::Nothing => {
+                            assert_eq!(most_conflicts(&solver), 0);
+                            break;
+                        }
+                    };
+                    if rng.gen() {
+                        let (vv, _) = solver.merge(v1, v2);
+                        changes.push((v1, v2, Err(vv)));
+                    } else {
+                        let (edge, _) = solver.cut(v1, v2);
+                        changes.push((v1, v2, Ok(edge)));
+                    }
+                }
+                while let Some((v1, v2, change)) = changes.pop() {
+                    match change {
+                        Ok(edge) => solver.un_cut(v1, v2, edge),
+                        Err(vv) => solver.un_merge(v1, v2, vv),
+                    }
+                    if !incremental {
+                        solver.packing.pack(&solver.graph);
+                    }
+                    if let EdgeMod::Merge(v1, v2) | EdgeMod::Cut(v1, v2) = solver.best_edge() {
+                        assert_eq!(
+                            solver.packing.edge_conflicts[[v1, v2]],
+                            most_conflicts(&solver)
+                        );
+                    }
+                }
+            }
+        }
+
+        // with the other components set aside the queue only gives pairs of the active one
+        for seed in 0..10 {
+            let (first, second) = (random_instance(7, seed), random_instance(9, seed + 20));
+            let shifted: Vec<_> = (second.lines().skip(1))
+                .map(|line| {
+                    let (v1, v2) = line.split_once(' ').unwrap();
+                    let v1: usize = v1.parse().unwrap();
+                    let v2: usize = v2.parse().unwrap();
+                    format!("{} {}\n", v1 + 7, v2 + 7)
+                })
+                .collect();
+            let edges = first.lines().count() - 1 + shifted.len();
+            let body = first.split_once('\n').unwrap().1;
+            let input = format!("p cep 16 {}\n{}{}", edges, body, shifted.concat());
+            let graph = load(input.as_bytes()).unwrap();
+            let mut solver = Solver::new(graph, SolverConfig::default());
+            assert!(solver.packing.queue.is_some());
+            let other_count = solver.isolate_component();
+            assert!(other_count > 0);
+            let mut first = 0;
+            // the first component, then the others
+            for _ in 0..2 {
+                match solver.best_edge() {
+                    EdgeMod::Merge(v1, v2) | EdgeMod::Cut(v1, v2) => {
+                        assert!(solver.graph.is_active[v1] && solver.graph.is_active[v2]);
+                        assert_eq!(
+                            solver.packing.edge_conflicts[[v1, v2]],
+                            most_conflicts(&solver)
+                        );
+                    }
+                    EdgeMod::Nothing => assert_eq!(most_conflicts(&solver), 0),
+                }
+                if first == 0 {
+                    first = solver.other_component(other_count);
+                }
+            }
+            solver.other_component(first);
+            solver.all_components(other_count);
+            assert_eq!(solver.graph.active.len(), 16);
+        }
+
+        // the other rules do not look at the queue, so it is not kept
+        let graph = load(random_instance(8, 0).as_bytes()).unwrap();
+        let config = SolverConfig {
+            branching: Branching::Vertex,
+            ..SolverConfig::default()
+        };
+        assert!(Solver::new(graph, config).packing.queue.is_none());
+    }
+
+    #[test]
+    fn deep_search() {
+        // every path of three vertices is a component that needs a cut, the search nests as deep
+        // as there are paths. it runs on a small stack, only its own steps can grow that deep
+        let paths = 150;
+        let mut input = format!("p cep {} {}\n", 3 * paths, 2 * paths);
+        for path in 0..paths {
+            input += &format!("{} {}\n", 3 * path + 1, 3 * path + 2);
+            input += &format!("{} {}\n", 3 * path + 2, 3 * path + 3);
+        }
+        let graph = load(input.as_bytes()).unwrap();
+        for branch_components in [false, true] {
+            let config = SolverConfig {
+                heuristic_rounds: 0,
+                branch_components,
+                ..SolverConfig::default()
+            };
+            let mut solver = Solver::new(graph.clone(), config);
+            let solver = thread::Builder::new()
+                .stack_size(64 * 1024)
+                .spawn(move || {
+                    solver.solve();
+                    solver
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+            assert_eq!(solver.upper, paths);
+            assert_eq!(solution_cost(&graph, &solver.best), paths);
+        }
+    }
+
+    #[test]
+    fn dynamic() {
+        for seed in 0..20 {
+            let input = seeded_instance(8, seed);
+            let graph = load(input.as_bytes()).unwrap();
+            let mut dynamic = DynamicSolver::new(graph.clone(), SolverConfig::default());
+            assert_eq!(dynamic.cost, brute_force(&graph));
+
+            let mut rng = StdRng::seed_from_u64(seed);
+            for _ in 0..5 {
+                let edits: Vec<_> = (0..rng.gen_range(1..4))
+                    .map(|_| {
+                        let v1 = rng.gen_range(0..8);
+                        let v2 = (v1 + rng.gen_range(1..8)) % 8;
+                        (v1, v2, rng.gen_range(-3..=3))
+                    })
+                    .filter(|&(_, _, weight)| weight != 0)
+                    .collect();
+                let cost = dynamic.update(&edits).unwrap();
+                assert_eq!(cost, brute_force(&dynamic.graph));
+                assert_eq!(solution_cost(&dynamic.graph, &dynamic.best), cost);
+            }
+        }
+
+        // cliques joined and split by the edits, against solving every graph from scratch
+        for (seed, variant, incremental) in [
+            (0, ProblemVariant::Editing, true),
+            (1, ProblemVariant::Editing, false),
+            (2, ProblemVariant::Deletion, true),
+        ] {
+            let size = 30;
+            let mut input = vec![];
+            for v1 in 0..size {
+                for v2 in v1 + 1..size {
+                    if v1 / 5 == v2 / 5 {
+                        input.push(format!("{} {}\n", v1 + 1, v2 + 1));
+                    }
+                }
+            }
+            let input = format!("p cep {} {}\n{}", size, input.len(), input.concat());
+            let config = SolverConfig {
+                variant,
+                incremental,
+                improve_packing: Improvement::Every(2),
+                ..SolverConfig::default()
+            };
+            let mut dynamic = DynamicSolver::new(load(input.as_bytes()).unwrap(), config);
+            assert_eq!((dynamic.cost, dynamic.resolved), (0, 6));
+            let mut rng = StdRng::seed_from_u64(seed);
+            for _ in 0..20 {
+                let v1 = rng.gen_range(0..size);
+                let v2 = (v1 + rng.gen_range(1..size)) % size;
+                let weight = if rng.gen_bool(0.5) { 2 } else { -2 };
+                let cost = dynamic.update(&[(v1, v2, weight)]).unwrap();
+                assert!(dynamic.resolved <= 2);
+                let (_, optimal, _) = solve_components(&dynamic.graph, config);
+                assert_eq!(cost, optimal, "seed {}", seed);
+                assert_eq!(solution_cost(&dynamic.graph, &dynamic.best), cost);
+            }
+        }
+
+        // an edit in one triangle leaves the other one alone
+        let graph = load("p cep 6 6\n1 2\n2 3\n1 3\n4 5\n5 6\n4 6\n".as_bytes()).unwrap();
+        let mut dynamic = DynamicSolver::new(graph, SolverConfig::default());
+        assert_eq!((dynamic.cost, dynamic.resolved), (0, 2));
+        assert_eq!(dynamic.update(&[(0, 1, -1)]), Ok(1));
+        assert_eq!(dynamic.resolved, 1);
+        assert_eq!(dynamic.update(&[(0, 1, 1)]), Ok(0));
+        assert_eq!(dynamic.resolved, 1);
+        // the edits are checked before any of them is made
+        for (edit, error) in [
+            ((2, 2, 1), EditError::SelfLoop { v: 2 }),
+            ((0, 6, 1), EditError::OutOfRange { v: 6 }),
+            ((1, 4, 0), EditError::ZeroWeight { v1: 1, v2: 4 }),
+            (
+                (1, 4, -i32::MAX),
+                EditError::BadWeight {
+                    v1: 1,
+                    v2: 4,
+                    weight: -i32::MAX,
+                },
+            ),
+            (
+                (1, 4, i32::MAX),
+                EditError::BadWeight {
+                    v1: 1,
+                    v2: 4,
+                    weight: i32::MAX,
+                },
+            ),
+        ] {
+            assert_eq!(dynamic.update(&[(0, 3, 1), edit]), Err(error));
+        }
+        assert_eq!(dynamic.graph[[0, 3]].weight, -1);
+        assert_eq!(dynamic.update(&[(1, 4, -MAX_WEIGHT)]), Ok(0));
+        let mut graph = load("p cep 3 2\n1 2\n2 3\n".as_bytes()).unwrap();
+        let constraints = Constraints {
+            must_link: vec![],
+            cannot_link: vec![(0, 2)],
+        };
+        graph.constrain(&constraints).unwrap();
+        let mut dynamic = DynamicSolver::new(graph, SolverConfig::default());
+        assert_eq!(dynamic.cost, 1);
+        assert_eq!(
+            dynamic.update(&[(2, 0, 3)]),
+            Err(EditError::Fixed { v1: 2, v2: 0 })
+        );
+    }
+
+    #[test]
+    fn variants() {
+        for seed in 0..30 {
+            let input = if seed % 2 == 0 {
+                random_instance(7, seed)
+            } else {
+                random_weighted_instance(7, seed)
+            };
+            let graph = load(input.as_bytes()).unwrap();
+            for variant in [ProblemVariant::Deletion, ProblemVariant::Completion] {
+                // the edits the variant does not allow get large weights
+                let mut forced = graph.clone();
+                for v1 in 0..7 {
+                    for v2 in v1 + 1..7 {
+                        let weight = &mut forced[[v1, v2]].weight;
+                        match variant {
+                            ProblemVariant::Deletion if *weight <= 0 => *weight = -1000,
+                            ProblemVariant::Completion if *weight > 0 => *weight = 1000,
+                            _ => {}
+                        }
+                    }
+                }
+                let optimal = brute_force(&forced);
+
+                for threads in [1, 3] {
+                    let config = SolverConfig {
+                        variant,
+                        threads,
+                        ..SolverConfig::default()
+                    };
+                    let kernel = Kernel::for_config(graph.clone(), &config);
+                    let mut solver = Solver::new(kernel.graph.clone(), config);
+                    solver.solve();
+                    assert!(solver.optimal);
+                    assert!(solver.stats.root_lower <= solver.upper, "seed {}", seed);
+                    assert_eq!(kernel.cost + solver.upper, optimal, "seed {}", seed);
+
+                    let lifted = kernel.lift(&solver.best);
+                    for v1 in 0..7 {
+                        for v2 in v1 + 1..7 {
+                            let edge = graph[[v1, v2]].weight > 0;
+                            let joined = lifted.root(v1) == lifted.root(v2);
+                            match variant {
+                                ProblemVariant::Deletion => assert!(edge || !joined),
+                                _ => assert!(!edge || joined),
+                            }
+                        }
+                    }
+                    let mut out = Vec::new();
+                    write_solution(&graph, &lifted, &mut out).unwrap();
+                    assert_eq!(verify(&graph, &out[..]).unwrap(), optimal);
+                }
+                let (_, cost, _) = solve_components(
+                    &graph,
+                    SolverConfig {
+                        variant,
+                        ..SolverConfig::default()
+                    },
+                );
+                assert_eq!(cost, optimal);
+            }
+        }
+
+        // the path can only be completed by joining 1 and 3, which are kept apart
+        let mut graph = load("p cep 3 2\n1 2\n2 3\n".as_bytes()).unwrap();
+        let cannot = Constraints {
+            must_link: vec![],
+            cannot_link: vec![(0, 2)],
+        };
+        graph.constrain(&cannot).unwrap();
+        for variant in [ProblemVariant::Deletion, ProblemVariant::Completion] {
+            let config = SolverConfig {
+                variant,
+                ..SolverConfig::default()
+            };
+            let mut solver = Solver::new(graph.clone(), config);
+            solver.solve();
+            let completion = variant == ProblemVariant::Completion;
+            assert_eq!(solver.infeasible, completion);
+            assert_eq!(solver.optimal, !completion);
+            let upper = if completion { u32::MAX } else { 1 };
+            assert_eq!(solver.upper, upper);
+            assert_eq!(solver.count_optimal(), (2 - completion as u64 * 2, true));
+            assert_eq!(solve_components(&graph, config).1, upper);
+        }
+    }
+
+    #[test]
+    fn heuristic_upper() {
+        for seed in 0..20 {
+            let graph = load(random_instance(7, seed).as_bytes()).unwrap();
+            let solver = Solver::new(graph.clone(), SolverConfig::default());
+            assert_eq!(solver.upper, solution_cost(&graph, &solver.best));
+            assert!(solver.upper >= brute_force(&graph));
+            assert!(solver.upper <= graph.edge_weight());
+        }
+    }
+
+    #[test]
+    fn improve_clustering() {
+        for seed in 0..10 {
+            let graph = load(random_instance(7, seed).as_bytes()).unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+            let timer = {
+                let stop = Arc::clone(&stop);
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    stop.store(true, Ordering::Relaxed)
+                })
+            };
+            let mut costs = vec![];
+            improve(&graph, seed, &stop, |clusters, cost| {
+                let output = Graph::from_clusters(&graph, clusters);
+                assert_eq!(solution_cost(&graph, &output), cost as u32);
+                costs.push(cost);
+            });
+            timer.join().unwrap();
+            assert!(costs.windows(2).all(|w| w[0] > w[1]));
+            assert!(*costs.last().unwrap() >= brute_force(&graph) as u64);
+        }
+    }
+
+    #[test]
+    fn verify_solutions() {
+        let graph = load("p cep 4 4\n1 2\n2 3\n1 3\n3 4\n".as_bytes()).unwrap();
+        assert_eq!(verify(&graph, "3 4\n".as_bytes()).unwrap(), 1);
+        assert_eq!(verify(&graph, "1 4\n4 2\n".as_bytes()).unwrap(), 2);
+        assert!(matches!(
+            verify(&graph, "".as_bytes()),
+            Err(Violation::NotClique { .. })
+        ));
+        assert!(matches!(
+            verify(&graph, "3 4\n4 3\n".as_bytes()),
+            Err(Violation::EditedTwice { line: 2, .. })
+        ));
+        assert!(matches!(
+            verify(&graph, "3 5\n".as_bytes()),
+            Err(Violation::OutOfRange { line: 1, .. })
+        ));
+        assert!(matches!(
+            verify(&graph, "3\n".as_bytes()),
+            Err(Violation::Malformed { line: 1, .. })
+        ));
+
+        for seed in 0..10 {
+            let graph = load(random_instance(7, seed).as_bytes()).unwrap();
+            let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+            solver.solve();
+            let mut out = Vec::new();
+            write_solution(&solver.graph, &solver.best, &mut out).unwrap();
+            assert_eq!(verify(&graph, &out[..]).unwrap(), solver.upper);
+        }
+    }
+
+    #[test]
+    fn decide() {
+        for seed in 0..20 {
+            let graph = load(random_instance(8, seed).as_bytes()).unwrap();
+            let optimal = brute_force(&graph);
+            let config = SolverConfig {
+                heuristic_rounds: 0,
+                ..SolverConfig::default()
+            };
+            let mut solver = Solver::new(graph.clone(), config);
+            if optimal > 0 {
+                assert_eq!(solver.decide(optimal - 1), Some(false));
+                assert_eq!(solver.upper, graph.edge_weight());
+            }
+            assert_eq!(solver.decide(optimal), Some(true));
+            assert_eq!(solver.upper, optimal);
+            assert_eq!(solution_cost(&graph, &solver.best), optimal);
+        }
+    }
+
+    #[test]
+    fn time_limit() {
+        let graph = load(random_instance(7, 0).as_bytes()).unwrap();
+        let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+        solver.cancel = Some(Arc::new(AtomicBool::new(true)));
+        solver.solve();
+        assert!(!solver.optimal);
+        assert_eq!(solver.upper, solution_cost(&graph, &solver.best));
+
+        solver.cancel = None;
+        solver.solve();
+        assert!(solver.optimal);
+        assert_eq!(solver.upper, brute_force(&graph));
+
+        let graph = load(random_instance(60, 0).as_bytes()).unwrap();
+        let mut solver = Solver::new(graph, SolverConfig::default());
+        solver.deadline = Some(Instant::now() + Duration::from_millis(50));
+        solver.solve();
+        let cost = write_solution(&solver.graph, &solver.best, io::sink()).unwrap();
+        assert_eq!(solver.upper, cost);
+
+        // a stopped search without any solution keeps it that way
+        let graph = load("p cep 6 4\n1 2\n2 3\n4 5\n5 6\n".as_bytes()).unwrap();
+        let config = SolverConfig {
+            cluster_count: ClusterCount::Exactly(4),
+            max_cluster_size: Some(1),
+            ..SolverConfig::default()
+        };
+        let mut solver = Solver::new(graph, config);
+        solver.cancel = Some(Arc::new(AtomicBool::new(true)));
+        solver.solve();
+        assert_eq!(solver.upper, u32::MAX);
+        assert!(!solver.optimal && !solver.infeasible);
+        assert_eq!(solver.decide(3), None);
+        assert_eq!(solver.upper, u32::MAX);
+    }
+
+    #[test]
+    fn kernel_lift() {
+        for seed in 0..40 {
+            let size = 6 + seed as usize % 4;
+            let input = if seed % 2 == 0 {
+                random_instance(size, seed)
+            } else {
+                random_weighted_instance(size, seed)
+            };
+            let graph = load(input.as_bytes()).unwrap();
+            let kernel = Kernel::new(graph.clone());
+            let mut solver = Solver::new(kernel.graph.clone(), SolverConfig::default());
+            solver.solve();
+            assert_eq!(
+                kernel.cost + solver.upper,
+                brute_force(&graph),
+                "seed {}",
+                seed
+            );
+
+            let mut out = Vec::new();
+            let lifted = kernel.lift(&solver.best);
+            let cost = write_solution(&graph, &lifted, &mut out).unwrap();
+            assert_eq!(cost, kernel.cost + solver.upper);
+            assert_eq!(verify(&graph, &out[..]).unwrap(), cost);
+        }
+
+        // only the edges to 3 leave the closed neighbourhood of the critical clique of 1 and 6,
+        // so the 2k rule puts it in one cluster. the heavy edge rule then adds 3 to it
+        let edges = "1 2\n1 4\n1 5\n1 6\n2 3\n2 4\n2 5\n2 6\n3 4\n3 5\n4 5\n4 6\n5 6\n";
+        let graph = load(format!("p cep 6 13\n{}", edges).as_bytes()).unwrap();
+        let kernel = Kernel::new(graph.clone());
+        assert!(kernel.graph.active.is_empty());
+        assert_eq!(kernel.cost, 2);
+        assert_eq!(kernel.cost, brute_force(&graph));
+    }
+
+    #[test]
+    fn sparse() {
+        for seed in 0..30 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let size = 6 + seed as usize % 4;
+            let mut pairs = Vec::new();
+            for v1 in 1..=size {
+                for v2 in v1 + 1..=size {
+                    if rng.gen_bool(0.4) {
+                        let weight = rng.gen_range(1..=3) * if rng.gen_bool(0.7) { 1 } else { -1 };
+                        pairs.push(format!("{} {} {}\n", v1, v2, weight));
+                    }
+                }
+            }
+            let input = if seed % 2 == 0 {
+                random_instance(size, seed)
+            } else {
+                format!("p wcep {} {} -2\n{}", size, pairs.len(), pairs.concat())
+            };
+            let graph = load_sparse(input.as_bytes()).unwrap();
+            assert!(graph.edges.base().is_some());
+            let dense = load(input.as_bytes()).unwrap();
+            let optimal = brute_force(&dense);
+            assert_eq!(brute_force(&graph), optimal);
+
+            // the edits of a clustering are found through the stored pairs and inside the clusters
+            let clusters: Vec<_> = (0..size * 2).map(|_| rng.gen_range(0..3)).collect();
+            assert_eq!(
+                clustering_cost(&graph, &clusters),
+                clustering_cost(&dense, &clusters)
+            );
+            let output = Graph::from_clusters(&graph, &clusters);
+            let (mut written, mut dense_written) = (vec![], vec![]);
+            write_solution(&graph, &output, &mut written).unwrap();
+            write_solution(&dense, &output, &mut dense_written).unwrap();
+            assert_eq!(written, dense_written);
+            assert_eq!(graph.edge_weight(), dense.edge_weight());
+
+            for (branch_components, incremental) in [(false, true), (true, true), (false, false)] {
+                let config = SolverConfig {
+                    branch_components,
+                    incremental,
+                    improve_packing: Improvement::Every(1),
+                    ..SolverConfig::default()
+                };
+                let mut solver = Solver::new(graph.clone(), config);
+                solver.solve();
+                assert_eq!(solver.upper, optimal, "seed {}", seed);
+                assert_eq!(solution_cost(&graph, &solver.best), optimal);
+            }
+
+            let kernel = Kernel::new(graph.clone());
+            let mut solver = Solver::new(kernel.graph.clone(), SolverConfig::default());
+            solver.solve();
+            assert_eq!(kernel.cost + solver.upper, optimal, "seed {}", seed);
+            let (best, cost, _) = solve_components(&kernel.graph, SolverConfig::default());
+            assert_eq!(kernel.cost + cost, optimal, "seed {}", seed);
+            assert_eq!(solution_cost(&graph, &kernel.lift(&best)), optimal);
+        }
+
+        // too large for brute force, the packing only follows the stored edges here
+        for seed in 0..3 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let size = 30;
+            let mut pairs = Vec::new();
+            for v1 in 1..=size {
+                for v2 in v1 + 1..=size {
+                    if rng.gen_bool(0.1) {
+                        pairs.push(format!("{} {} {}\n", v1, v2, rng.gen_range(1..=3)));
+                    }
+                }
+            }
+            let input = format!("p wcep {} {} -1\n{}", size, pairs.len(), pairs.concat());
+            let mut dense = Solver::new(load(input.as_bytes()).unwrap(), SolverConfig::default());
+            dense.solve();
+            let graph = load_sparse(input.as_bytes()).unwrap();
+            let mut solver = Solver::new(graph.clone(), SolverConfig::default());
+            solver.solve();
+            assert_eq!(solver.upper, dense.upper, "seed {}", seed);
+            assert_eq!(solution_cost(&graph, &solver.best), dense.upper);
+
+            // the pairs without a value of their own are summed up by the sizes of the clusters,
+            // merged vertices and forbidden defaults included
+            let mut merged = graph.clone();
+            for _ in 0..10 {
+                let v1 = merged.active[0];
+                let v2 = merged.positive(v1).find(|&v2| v2 != v1);
+                if let Some(v2) = v2 {
+                    merged.merge(v1, v2);
+                }
+            }
+            let mut restricted = merged.clone();
+            restricted.restrict(ProblemVariant::Deletion);
+            for graph in [&merged, &restricted] {
+                let len = graph.vertex_merged.len();
+                let mut clusters: Vec<_> = (0..len).map(|_| rng.gen_range(0..3)).collect();
+                local_search(graph, &mut clusters, &AtomicBool::new(false));
+                let cost = clustering_cost(graph, &clusters);
+                for &v in &graph.active {
+                    for c in 0..len {
+                        let mut moved = clusters.clone();
+                        moved[v] = c;
+                        assert!(clustering_cost(graph, &moved) >= cost, "seed {}", seed);
+                    }
+                }
+            }
         }
     }
 
@@ -0,0 +1,445 @@
+use std::sync::Arc;
+
+use crate::{
+    branch::EdgeMod,
+    config::{LpBound, SolverConfig},
+    graph::{Edge, Graph},
+    matrix::Storage,
+    search::Solver,
+};
+
+// the lower bound from the lp relaxation of cluster editing. every free pair in a conflict triple
+// has a variable x that is 1 when the pair ends up in one cluster, an edge costs its weight times
+// 1 - x and a non-edge its weight times x, and every triple of such pairs has x12 + x23 - x13 <= 1.
+// only edges need x <= 1, larger values of the others never help. the other pairs cost nothing
+// here, so the bound stays below the cost of every clustering. the triangle inequalities are added
+// as cuts and the dual simplex keeps the reduced costs nonnegative, so its objective is a bound at
+// every step. the limits on its size and work are in `SolverConfig`, see `LpBound`
+
+const EPS: f64 = 1e-9;
+const MAX_ROUNDS: usize = 100;
+// the most violated cuts added in one round
+const MAX_CUTS: usize = 500;
+
+// what the relaxation knows of a pair of its vertices
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pair {
+    // the index of its variable
+    Free(usize),
+    // forbidden, so it is apart in every clustering
+    Apart,
+    // in no conflict triple, no variable or cut has it
+    Left,
+}
+
+#[derive(Clone)]
+struct Tableau {
+    // row i is sum rows[i][j] * x_j = rhs[i] with coefficient 1 for `basic[i]`, which is 0 in the
+    // other rows
+    rows: Vec<Vec<f64>>,
+    rhs: Vec<f64>,
+    basic: Vec<usize>,
+    // the objective is `value` + sum costs[j] * x_j, 0 for the basic columns
+    costs: Vec<f64>,
+    value: f64,
+    // whether the column is the slack of a cut, which is dropped again when it does not bind
+    cut_slack: Vec<bool>,
+    pivots: usize,
+}
+
+impl Tableau {
+    fn pivot(&mut self, r: usize, c: usize) {
+        self.pivots += 1;
+        let factor = self.rows[r][c];
+        for a in &mut self.rows[r] {
+            *a /= factor;
+        }
+        self.rhs[r] /= factor;
+        let row = self.rows[r].clone();
+        let rhs = self.rhs[r];
+        for i in 0..self.rows.len() {
+            let factor = self.rows[i][c];
+            if i != r && factor != 0. {
+                for (a, b) in self.rows[i].iter_mut().zip(&row) {
+                    *a -= factor * b;
+                }
+                self.rhs[i] -= factor * rhs;
+            }
+        }
+        let factor = self.costs[c];
+        for (a, b) in self.costs.iter_mut().zip(&row) {
+            *a -= factor * b;
+        }
+        self.value += factor * rhs;
+        self.basic[r] = c;
+    }
+
+    // add `sum a_j * x_j <= rhs` with a slack of its own
+    fn add_row(&mut self, coefficients: &[(usize, f64)], mut rhs: f64, cut: bool) {
+        let slack = self.costs.len();
+        for row in &mut self.rows {
+            row.push(0.);
+        }
+        self.costs.push(0.);
+        self.cut_slack.push(cut);
+        let mut row = vec![0.; slack + 1];
+        row[slack] = 1.;
+        for &(j, a) in coefficients {
+            row[j] += a;
+        }
+        // the basic columns have to leave the new row
+        for i in 0..self.rows.len() {
+            let factor = row[self.basic[i]];
+            if factor != 0. {
+                for (a, b) in row.iter_mut().zip(&self.rows[i]) {
+                    *a -= factor * b;
+                }
+                rhs -= factor * self.rhs[i];
+            }
+        }
+        self.rows.push(row);
+        self.rhs.push(rhs);
+        self.basic.push(slack);
+    }
+
+    // drop the cuts whose slack is basic and positive. the slack is only in its row, so the row
+    // and the column go without changing the rest
+    fn drop_loose(&mut self) {
+        let loose: Vec<_> = (0..self.rows.len())
+            .map(|i| self.cut_slack[self.basic[i]] && self.rhs[i] > EPS)
+            .collect();
+        if !loose.contains(&true) {
+            return;
+        }
+        let mut keep = vec![true; self.costs.len()];
+        for i in (0..self.rows.len()).filter(|&i| loose[i]) {
+            keep[self.basic[i]] = false;
+        }
+        // the new index of every column that is kept
+        let mut index = vec![0; keep.len()];
+        let mut next = 0;
+        for (j, &kept) in keep.iter().enumerate() {
+            index[j] = next;
+            next += kept as usize;
+        }
+        retain(&mut self.rows, &loose, false);
+        retain(&mut self.rhs, &loose, false);
+        retain(&mut self.basic, &loose, false);
+        for row in &mut self.rows {
+            retain(row, &keep, true);
+        }
+        retain(&mut self.costs, &keep, true);
+        retain(&mut self.cut_slack, &keep, true);
+        for j in &mut self.basic {
+            *j = index[*j];
+        }
+    }
+
+    // pivot until the primal is feasible, returns false when it runs out of pivots or the
+    // objective reaches `stop`
+    fn dual_simplex(&mut self, max_pivots: usize, stop: f64) -> bool {
+        loop {
+            if self.pivots >= max_pivots || self.value >= stop {
+                return false;
+            }
+            let r = (0..self.rhs.len())
+                .filter(|&i| self.rhs[i] < -EPS)
+                .min_by(|&i1, &i2| self.rhs[i1].total_cmp(&self.rhs[i2]));
+            let r = match r {
+                Some(r) => r,
+                None => return true,
+            };
+            let c = (0..self.costs.len())
+                .filter(|&j| self.rows[r][j] < -EPS)
+                .min_by(|&j1, &j2| {
+                    let ratio = |j: usize| self.costs[j].max(0.) / -self.rows[r][j];
+                    ratio(j1).total_cmp(&ratio(j2))
+                });
+            match c {
+                Some(c) => self.pivot(r, c),
+                // x = 0 is feasible, so this is only rounding
+                None => return false,
+            }
+        }
+    }
+
+    fn values(&self, len: usize) -> Vec<f64> {
+        let mut values = vec![0.; len];
+        for (i, &j) in self.basic.iter().enumerate() {
+            if j < len {
+                values[j] = self.rhs[i];
+            }
+        }
+        values
+    }
+
+    fn entries(&self, rows: usize) -> usize {
+        (self.rows.len() + rows) * (self.costs.len() + rows)
+    }
+}
+
+// keep the values whose flag is `keep`
+fn retain<T>(values: &mut Vec<T>, flags: &[bool], keep: bool) {
+    let mut j = 0;
+    values.retain(|_| {
+        j += 1;
+        flags[j - 1] == keep
+    });
+}
+
+// the relaxation of a graph, kept along the search. the variables and cuts stay those of the graph
+// it is built on, a merge or cut below it fixes the variable of its pair and adds its cost to
+// `offset`. with all cuts that is the relaxation of the graph below the edits
+#[derive(Clone)]
+pub struct Relaxation {
+    // every pair of vertices of the relaxation
+    pairs: Arc<Vec<Vec<Pair>>>,
+    // the vertices with a variable, only their triples can be cuts
+    cut_vertices: Arc<Vec<usize>>,
+    len: usize,
+    max_entries: usize,
+    max_pivots: usize,
+    tableau: Tableau,
+    // the active vertices of the graph, sorted, with the vertex of the relaxation they stand for
+    active: Vec<(usize, usize)>,
+    offset: u32,
+}
+
+impl Relaxation {
+    // `None` when the graph has no free pairs in conflict triples or more than `config.lp_pairs`.
+    // it stops early once the bound reaches `enough`
+    pub fn new<S: Storage<Edge>>(
+        graph: &Graph<S>,
+        config: &SolverConfig,
+        enough: u32,
+    ) -> Option<Self> {
+        let active = &graph.active;
+        let size = active.len();
+        // every conflict triple has an edge, with one of the other pairs an edge too
+        let mut in_triple = vec![vec![false; size]; size];
+        for (i1, &v1) in active.iter().enumerate() {
+            for (i2, &v2) in active.iter().enumerate().skip(i1 + 1) {
+                if graph[[v1, v2]].weight <= 0 {
+                    continue;
+                }
+                for (i3, &v3) in active.iter().enumerate() {
+                    if (graph[[v1, v3]].weight > 0) != (graph[[v2, v3]].weight > 0) {
+                        for (a, b) in [(i1, i2), (i1, i3), (i2, i3)] {
+                            in_triple[a][b] = true;
+                            in_triple[b][a] = true;
+                        }
+                    }
+                }
+            }
+        }
+        let mut pairs = vec![vec![Pair::Left; size]; size];
+        let mut weights = vec![];
+        for (i1, &v1) in active.iter().enumerate() {
+            for (i2, &v2) in active.iter().enumerate().skip(i1 + 1) {
+                let edge = graph[[v1, v2]];
+                let pair = if edge.fixed {
+                    Pair::Apart
+                } else if in_triple[i1][i2] {
+                    weights.push(edge.weight as f64);
+                    Pair::Free(weights.len() - 1)
+                } else {
+                    continue;
+                };
+                pairs[i1][i2] = pair;
+                pairs[i2][i1] = pair;
+            }
+        }
+        let len = weights.len();
+        if len == 0 || len > config.lp_pairs {
+            return None;
+        }
+        let cut_vertices = (0..size)
+            .filter(|&i1| pairs[i1].iter().any(|pair| matches!(pair, Pair::Free(_))))
+            .collect();
+
+        // every edge starts in one cluster and every non-edge apart, which is optimal without cuts
+        let edges: Vec<_> = (0..len).filter(|&j| weights[j] > 0.).collect();
+        let columns = len + edges.len();
+        let mut tableau = Tableau {
+            rows: vec![],
+            rhs: vec![],
+            basic: vec![],
+            costs: weights.iter().map(|&w| w.abs()).collect(),
+            value: 0.,
+            cut_slack: vec![false; columns],
+            pivots: 0,
+        };
+        tableau.costs.resize(columns, 0.);
+        for (i, &j) in edges.iter().enumerate() {
+            let mut row = vec![0.; columns];
+            row[j] = 1.;
+            row[len + i] = 1.;
+            tableau.rows.push(row);
+            tableau.rhs.push(1.);
+            tableau.basic.push(j);
+            tableau.costs[len + i] = weights[j];
+            tableau.costs[j] = 0.;
+        }
+
+        let mut active: Vec<_> = active.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        active.sort_unstable();
+        let mut relaxation = Self {
+            pairs: Arc::new(pairs),
+            cut_vertices: Arc::new(cut_vertices),
+            len,
+            max_entries: config.lp_entries,
+            max_pivots: config.lp_pivots,
+            tableau,
+            active,
+            offset: 0,
+        };
+        relaxation.solve(enough);
+        Some(relaxation)
+    }
+
+    // the relaxation below `edit`, which took `cost`, from this one. `graph` is the graph after
+    // the edit, `None` when this is no relaxation of the graph before it
+    pub fn child<S: Storage<Edge>>(
+        &self,
+        graph: &Graph<S>,
+        edit: EdgeMod,
+        cost: u32,
+        enough: u32,
+    ) -> Option<Self> {
+        let (v1, v2) = match edit {
+            EdgeMod::Merge(v1, v2) | EdgeMod::Cut(v1, v2) => (v1, v2),
+            EdgeMod::Nothing => return None,
+        };
+        let merged = matches!(edit, EdgeMod::Merge(..)).then(|| graph.root(v1));
+        let mut before = graph.active.clone();
+        if let Some(vv) = merged {
+            before.retain(|&v| v != vv);
+            before.extend([v1, v2]);
+        }
+        before.sort_unstable();
+        if !before.iter().eq(self.active.iter().map(|(v, _)| v)) {
+            return None;
+        }
+
+        let find = |v: usize| {
+            let i = self.active.binary_search_by_key(&v, |&(v, _)| v).unwrap();
+            self.active[i].1
+        };
+        let (i1, i2) = (find(v1), find(v2));
+        let mut child = self.clone();
+        child.offset = self.offset.saturating_add(cost);
+        // the other pairs of merged vertices follow through the cuts
+        match (self.pairs[i1][i2], merged) {
+            (Pair::Free(j), Some(_)) => child.tableau.add_row(&[(j, -1.)], -1., false),
+            (Pair::Free(j), None) => child.tableau.add_row(&[(j, 1.)], 0., false),
+            _ => {}
+        }
+        if let Some(vv) = merged {
+            child.active.retain(|&(v, _)| v != v1 && v != v2);
+            let i = child.active.partition_point(|&(v, _)| v < vv);
+            child.active.insert(i, (vv, i1));
+        }
+        child.solve(enough);
+        Some(child)
+    }
+
+    // add the most violated cuts in rounds, until none is left, the bound reaches `enough` or the
+    // limits are reached. the bound only grows, so a search that prunes with it can stop there
+    fn solve(&mut self, enough: u32) {
+        let stop = self.offset as f64 + enough as f64;
+        let (max_entries, max_pivots) = (self.max_entries, self.max_pivots);
+        let tableau = &mut self.tableau;
+        tableau.pivots = 0;
+        // a fixed pair leaves the primal infeasible first
+        if !tableau.dual_simplex(max_pivots, stop) {
+            return;
+        }
+        let vertices = &self.cut_vertices;
+        for _ in 0..MAX_ROUNDS {
+            tableau.drop_loose();
+            let x = tableau.values(self.len);
+            let pairs = &self.pairs;
+            // a pair without a variable could be in one cluster or not, unless it is forbidden
+            let value = |i1: usize, i2: usize| match pairs[i1][i2] {
+                Pair::Free(j) => Some(x[j]),
+                Pair::Apart => Some(0.),
+                Pair::Left => None,
+            };
+            let mut cuts = vec![];
+            for (k1, &i1) in vertices.iter().enumerate() {
+                for (k2, &i2) in vertices.iter().enumerate().skip(k1 + 1) {
+                    for &i3 in &vertices[k2 + 1..] {
+                        // each pair of the triple as the one that is subtracted
+                        for (a, b, c) in [(i1, i2, i3), (i1, i3, i2), (i2, i3, i1)] {
+                            let (ac, bc, ab) = match (value(a, c), value(b, c), value(a, b)) {
+                                (Some(ac), Some(bc), Some(ab)) => (ac, bc, ab),
+                                _ => continue,
+                            };
+                            let violation = ac + bc - ab - 1.;
+                            if violation > 1e-6 {
+                                let mut cut = vec![];
+                                for (pair, sign) in
+                                    [(pairs[a][c], 1.), (pairs[b][c], 1.), (pairs[a][b], -1.)]
+                                {
+                                    if let Pair::Free(j) = pair {
+                                        cut.push((j, sign));
+                                    }
+                                }
+                                cuts.push((violation, cut));
+                            }
+                        }
+                    }
+                }
+            }
+            cuts.sort_by(|(v1, _), (v2, _)| v2.total_cmp(v1));
+            let room = (1..=cuts.len().min(MAX_CUTS))
+                .take_while(|&count| tableau.entries(count) <= max_entries)
+                .count();
+            if room == 0 {
+                break;
+            }
+            for (_, cut) in &cuts[..room] {
+                tableau.add_row(cut, 1., true);
+            }
+            if !tableau.dual_simplex(max_pivots, stop) {
+                break;
+            }
+        }
+        tableau.drop_loose();
+    }
+
+    // the bound on the cost of the graph below the edits
+    pub fn lower(&self) -> u32 {
+        // the slack of an edge costs its weight, so the objective is the whole cost
+        let lower = self.tableau.value - self.offset as f64;
+        (lower - 1e-6 * lower.abs().max(1.)).ceil().max(0.) as u32
+    }
+}
+
+// the lower bound of the lp relaxation on the active vertices, 0 for graphs that are too large
+// for the limits of `config`
+pub fn lp_lower<S: Storage<Edge>>(graph: &Graph<S>, config: &SolverConfig) -> u32 {
+    Relaxation::new(graph, config, u32::MAX).map_or(0, |relaxation| relaxation.lower())
+}
+
+impl<S: Storage<Edge>> Solver<S> {
+    // whether the lp relaxation of the graph after `edit` still leaves room for its `cost` below
+    // `upper`, only checked after every merge and cut with `LpBound::Nodes`. the relaxation
+    // starts from the one of the node above when there is one
+    pub fn lp_fits(&mut self, edit: EdgeMod, cost: u32) -> bool {
+        if self.config.lp_bound != LpBound::Nodes {
+            return true;
+        }
+        let depth = self.depth;
+        let parent = self.relaxations.get(depth).and_then(Option::as_ref);
+        let enough = self.upper.saturating_sub(cost);
+        let relaxation = parent
+            .and_then(|parent| parent.child(&self.graph, edit, cost, enough))
+            .or_else(|| Relaxation::new(&self.graph, &self.config, enough));
+        let lower = relaxation.as_ref().map_or(0, Relaxation::lower);
+        // the node below replaces the ones of its siblings
+        self.relaxations.resize_with(depth + 1, || None);
+        self.relaxations.push(relaxation);
+        lower.saturating_add(cost) < self.upper
+    }
+}
@@ -1,14 +1,54 @@
-use std::ops::{Index, IndexMut};
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    fmt::Debug,
+    ops::{Index, IndexMut},
+};
 
-#[derive(Debug)]
-pub struct Matrix<T>(Vec<T>, usize);
+// a value for every pair of vertices. the graph and the packing are generic over it, so the
+// backend is chosen once when the graph is loaded
+pub trait Storage<T>:
+    Index<[usize; 2], Output = T> + IndexMut<[usize; 2]> + Clone + Debug + Send + Sync
+{
+    // a storage of the same kind for another value
+    type Like<U: Value>: Storage<U>;
 
-impl<T: Clone> Matrix<T> {
-    pub fn new(val: T, len: usize) -> Self {
-        Self(vec![val; len * len], len)
+    fn new(default: T, len: usize) -> Self;
+
+    fn like<U: Value>(&self, default: U, len: usize) -> Self::Like<U> {
+        Self::Like::new(default, len)
     }
+
+    // whether the pair has a value of its own, writing to a pair without one costs memory
+    fn stored(&self, index: [usize; 2]) -> bool;
+
+    // the value of the pairs without one of their own, `None` when every pair has one
+    fn base(&self) -> Option<T>;
+
+    // the value of a pair of `v` and a vertex standing for `size` vertices when it has none of its
+    // own, `None` when every pair has one
+    fn default_with(&self, v: usize, size: usize) -> Option<T>;
+
+    // the vertices that `v` has a stored pair with
+    fn neighbours(&self, v: usize) -> impl Iterator<Item = usize> + '_;
+
+    // the pair goes back to its default
+    fn remove(&mut self, index: [usize; 2]);
+
+    // `v` stands for `size` vertices, `scale` gives the default of a pair of vertices standing
+    // for `product` pairs. it is prepared for the pairs of `v` with itself and all of `others`
+    fn set_size<F: Fn(T, usize) -> T>(&mut self, v: usize, size: usize, others: &[usize], scale: F);
+
+    fn map_defaults<F: Fn(T) -> T>(&mut self, map: F);
 }
 
+// what can be stored for a pair
+pub trait Value: Copy + Debug + Send + Sync {}
+
+impl<T: Copy + Debug + Send + Sync> Value for T {}
+
+#[derive(Debug)]
+pub struct Matrix<T>(Vec<T>, usize);
+
 impl<T> Index<[usize; 2]> for Matrix<T> {
     type Output = T;
 
@@ -38,3 +78,136 @@ impl<T: Copy> Clone for Matrix<T> {
         self.0.copy_from_slice(&source.0);
     }
 }
+
+impl<T: Value> Storage<T> for Matrix<T> {
+    type Like<U: Value> = Matrix<U>;
+
+    fn new(default: T, len: usize) -> Self {
+        Self(vec![default; len * len], len)
+    }
+
+    fn stored(&self, _: [usize; 2]) -> bool {
+        true
+    }
+
+    fn base(&self) -> Option<T> {
+        None
+    }
+
+    fn default_with(&self, _: usize, _: usize) -> Option<T> {
+        None
+    }
+
+    fn neighbours(&self, _: usize) -> impl Iterator<Item = usize> + '_ {
+        0..self.1
+    }
+
+    // every pair keeps its value
+    fn remove(&mut self, _: [usize; 2]) {}
+
+    fn set_size<F: Fn(T, usize) -> T>(&mut self, _: usize, _: usize, _: &[usize], _: F) {}
+
+    fn map_defaults<F: Fn(T) -> T>(&mut self, _: F) {}
+}
+
+// only the pairs that were written to take memory, the others have a default value.
+// a merged vertex stands for `size` vertices, so the default of a pair depends on the
+// product of the sizes of its vertices
+#[derive(Debug, Clone)]
+pub struct Sparse<T> {
+    entries: HashMap<[usize; 2], T>,
+    // the vertices every vertex has an entry with, in order so loops over them are repeatable
+    adjacent: Vec<BTreeSet<usize>>,
+    size: Vec<usize>,
+    defaults: HashMap<usize, T>,
+}
+
+impl<T> Index<[usize; 2]> for Sparse<T> {
+    type Output = T;
+
+    fn index(&self, mut index: [usize; 2]) -> &Self::Output {
+        index.sort_unstable();
+        match self.entries.get(&index) {
+            Some(value) => value,
+            None => &self.defaults[&(self.size[index[0]] * self.size[index[1]])],
+        }
+    }
+}
+
+impl<T: Copy> IndexMut<[usize; 2]> for Sparse<T> {
+    fn index_mut(&mut self, mut index: [usize; 2]) -> &mut Self::Output {
+        index.sort_unstable();
+        match self.entries.entry(index) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.adjacent[index[0]].insert(index[1]);
+                self.adjacent[index[1]].insert(index[0]);
+                entry.insert(self.defaults[&(self.size[index[0]] * self.size[index[1]])])
+            }
+        }
+    }
+}
+
+impl<T: Value> Storage<T> for Sparse<T> {
+    type Like<U: Value> = Sparse<U>;
+
+    fn new(default: T, len: usize) -> Self {
+        let mut defaults = HashMap::new();
+        defaults.insert(1, default);
+        Self {
+            entries: HashMap::new(),
+            adjacent: vec![BTreeSet::new(); len],
+            size: vec![1; len],
+            defaults,
+        }
+    }
+
+    fn stored(&self, mut index: [usize; 2]) -> bool {
+        index.sort_unstable();
+        self.entries.contains_key(&index)
+    }
+
+    // the default of a pair of vertices that were not merged
+    fn base(&self) -> Option<T> {
+        Some(self.defaults[&1])
+    }
+
+    fn default_with(&self, v: usize, size: usize) -> Option<T> {
+        Some(self.defaults[&(self.size[v] * size)])
+    }
+
+    fn neighbours(&self, v: usize) -> impl Iterator<Item = usize> + '_ {
+        self.adjacent[v].iter().copied()
+    }
+
+    fn remove(&mut self, mut index: [usize; 2]) {
+        index.sort_unstable();
+        if self.entries.remove(&index).is_some() {
+            self.adjacent[index[0]].remove(&index[1]);
+            self.adjacent[index[1]].remove(&index[0]);
+        }
+    }
+
+    fn set_size<F: Fn(T, usize) -> T>(
+        &mut self,
+        v: usize,
+        size: usize,
+        others: &[usize],
+        scale: F,
+    ) {
+        self.size[v] = size;
+        let default = self.defaults[&1];
+        for &v2 in others.iter().chain(&[v]) {
+            let product = size * self.size[v2];
+            self.defaults
+                .entry(product)
+                .or_insert_with(|| scale(default, product));
+        }
+    }
+
+    fn map_defaults<F: Fn(T) -> T>(&mut self, map: F) {
+        for default in self.defaults.values_mut() {
+            *default = map(*default);
+        }
+    }
+}
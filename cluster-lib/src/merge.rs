@@ -1,37 +1,71 @@
 use std::cmp::{max, min};
 
-use crate::graph::{AllFrom, Edge, Graph};
+use crate::{
+    graph::{saturate, AllFrom, Edge, Graph},
+    matrix::Storage,
+};
 
-impl Graph {
+impl<S: Storage<Edge>> Graph<S> {
     // requires edge between vertices to be positive
     pub fn merge(&mut self, v1: usize, v2: usize) -> (usize, u32) {
         self.active.retain(|&v| v != v1 && v != v2);
+        self.is_active[v1] = false;
+        self.is_active[v2] = false;
 
         let vv = self.len;
         self.len += 1;
+        self.size[vv] = self.size[v1] + self.size[v2];
+        self.edges
+            .set_size(vv, self.size[vv], &self.active, |edge, product| {
+                edge.scale(product)
+            });
+        // only the stored pairs of `v1` or `v2` can differ from the defaults of the merged vertex
+        // or be in conflict, and the pairs of an earlier vertex with the same index are stale
+        let stored = self.edges.base().map(|_| {
+            let stale: Vec<_> = self.edges.neighbours(vv).collect();
+            for v3 in stale {
+                self.edges.remove([vv, v3]);
+            }
+            let mut thirds: Vec<_> = (self.edges.neighbours(v1))
+                .chain(self.edges.neighbours(v2))
+                .filter(|&v3| self.is_active[v3])
+                .collect();
+            thirds.sort_unstable();
+            thirds.dedup();
+            thirds
+        });
+        let all = if stored.is_none() {
+            Some(&self.active)
+        } else {
+            None
+        };
+        self.edges[[vv, vv]] = Edge::new(i32::MAX);
 
         let mut cost = max(0, -self[[v1, v2]].weight) as u32;
-        for &v3 in &self.active {
+        for &v3 in stored.iter().flatten().chain(all.into_iter().flatten()) {
             if -self[[v1, v3]].weight ^ -self[[v2, v3]].weight < 0 {
-                cost += min(self[[v1, v3]].weight.abs(), self[[v2, v3]].weight.abs()) as u32;
+                let weight = min(self[[v1, v3]].weight.abs(), self[[v2, v3]].weight.abs());
+                cost = cost.saturating_add(weight as u32);
             }
             self.edges[[vv, v3]] = if self[[v1, v3]].fixed || self[[v2, v3]].fixed {
                 Edge::none()
             } else {
-                Edge::new(self[[v1, v3]].weight + self[[v2, v3]].weight)
+                Edge::new(saturate(
+                    self[[v1, v3]].weight as i64 + self[[v2, v3]].weight as i64,
+                ))
             };
         }
 
-        self.active.push(vv);
+        self.activate(vv);
         self.vertex_merged[v1] = Some(vv);
         self.vertex_merged[v2] = Some(vv);
         (vv, cost)
     }
 
     pub fn un_merge(&mut self, v1: usize, v2: usize, vv: usize) {
-        self.active.retain(|&v| v != vv);
-        self.active.push(v1);
-        self.active.push(v2);
+        self.deactivate(vv);
+        self.activate(v1);
+        self.activate(v2);
         self.vertex_merged[v1] = None;
         self.vertex_merged[v2] = None;
         self.len -= 1;
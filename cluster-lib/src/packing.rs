@@ -1,36 +1,99 @@
 use std::cmp::min;
 
 use crate::{
-    graph::{AllFrom, Graph},
-    matrix::Matrix,
+    config::{Branching, ConflictCost, Improvement, ProblemVariant, SolverConfig},
+    graph::{AllFrom, Edge, Graph},
+    matrix::{Matrix, Storage},
+    queue::BucketQueue,
     triple::Triple,
 };
 
+// every pass of `improve` takes all triples, later passes rarely find much
+const MAX_PASSES: usize = 10;
+
 #[derive(Clone)]
-pub struct Packing {
+pub struct Packing<S: Storage<Edge> = Matrix<Edge>> {
     pub triples: Vec<Triple>,
-    pub edge_conflicts: Matrix<u32>,
-    pub edge_cost: Matrix<u32>,
+    pub edge_conflicts: S::Like<u32>,
+    pub edge_cost: S::Like<u32>,
     pub lower: u32,
+    // the fields of the solver config that the packing needs
+    incremental: bool,
+    lower_bound: bool,
+    conflict_cost: ConflictCost,
+    variant: ProblemVariant,
+    // improving needs the triples even when the packing is not kept up to date
+    keep_triples: bool,
+    // the largest score of a triple, a pair is in fewer triples than there are vertices so the
+    // scores of its triples add up to a u32
+    max_score: u32,
+    // the free pairs of active vertices by their conflicts, only for counted conflicts whose keys
+    // stay small and only when the branching rule takes its pairs from there
+    pub queue: Option<BucketQueue<S>>,
 }
 
-impl Packing {
-    pub fn new(len: usize) -> Self {
+impl<S: Storage<Edge>> Packing<S> {
+    pub fn new(graph: &Graph<S>, config: SolverConfig) -> Self {
+        let len = graph.vertex_merged.len();
         Self {
             triples: vec![],
-            edge_conflicts: Matrix::new(0, len),
-            edge_cost: Matrix::new(0, len),
+            edge_conflicts: graph.edges.like(0, len),
+            edge_cost: graph.edges.like(0, len),
             lower: 0,
+            incremental: config.incremental,
+            lower_bound: config.lower_bound,
+            conflict_cost: config.conflict_cost,
+            variant: config.variant,
+            keep_triples: config.incremental || config.improve_packing != Improvement::Off,
+            max_score: (u32::MAX as usize / len.max(1)) as u32,
+            queue: (config.conflict_cost == ConflictCost::Count
+                && config.branching == Branching::MostConflicts)
+                .then(|| BucketQueue::new(&graph.edges, len)),
         }
     }
 
-    pub fn pack(&mut self, graph: &Graph) {
+    pub fn pack(&mut self, graph: &Graph<S>) {
         self.triples.clear();
         self.lower = 0;
+        if graph.sparse_edges() {
+            // only the pairs of earlier triples are stored, and every conflict triple has one
+            // vertex with an edge to both others
+            for &v1 in &graph.active {
+                let stored: Vec<_> = (self.edge_conflicts.neighbours(v1))
+                    .filter(|&v2| v1 < v2 && graph.is_active[v2])
+                    .collect();
+                for v2 in stored {
+                    self.edge_conflicts[[v1, v2]] = 0;
+                    self.edge_cost[[v1, v2]] = 0;
+                    self.queue_pair(graph, v1, v2);
+                }
+            }
+            let mut triples = vec![];
+            for &v1 in &graph.active {
+                let near: Vec<_> = graph.positive(v1).filter(|&v2| v2 != v1).collect();
+                for (i2, &v2) in near.iter().enumerate() {
+                    for &v3 in &near[i2 + 1..] {
+                        let mut triple = [v1, v2, v3];
+                        triple.sort_unstable();
+                        triples.push(triple);
+                    }
+                }
+            }
+            // in about the order of the dense loops, the greedy packing depends on it
+            triples.sort_unstable();
+            for [v1, v2, v3] in triples {
+                self.add_triple(graph, v1, v2, v3);
+            }
+            return;
+        }
+
         for (i1, v1) in graph.active.all(0) {
             for (_, v2) in graph.active.all(i1) {
-                self.edge_conflicts[[v1, v2]] = 0;
-                self.edge_cost[[v1, v2]] = 0;
+                if self.edge_conflicts.stored([v1, v2]) {
+                    self.edge_conflicts[[v1, v2]] = 0;
+                    self.edge_cost[[v1, v2]] = 0;
+                    self.queue_pair(graph, v1, v2);
+                }
             }
         }
 
@@ -43,26 +106,90 @@ impl Packing {
         }
     }
 
-    pub fn add_vertex(&mut self, graph: &Graph, v1: usize) {
-        if cfg!(not(feature = "incremental")) {
+    // calls `f` on the pairs of active vertices that can make a conflict triple with `v1`, leaving
+    // out the pairs with `skip`. on the sparse path one of them has an edge to `v1`, and the other
+    // one an edge to `v1` or to it. they are sorted like in the dense loops
+    fn each_triple<F: FnMut(&mut Self, usize, usize)>(
+        &mut self,
+        graph: &Graph<S>,
+        v1: usize,
+        skip: usize,
+        mut f: F,
+    ) {
+        if !graph.sparse_edges() {
+            for (i2, v2) in graph.active.all(0) {
+                if v2 != v1 && v2 != skip {
+                    for (_, v3) in graph.active.all(i2) {
+                        if v3 != v1 && v3 != skip {
+                            f(self, v2, v3);
+                        }
+                    }
+                }
+            }
             return;
         }
-        for (i2, v2) in graph.active.all(0) {
-            if v1 != v2 {
-                for (_, v3) in graph.active.all(i2) {
-                    if v3 != v1 {
-                        self.add_triple(graph, v1, v2, v3);
-                    }
+        let near: Vec<_> = (graph.positive(v1))
+            .filter(|&v2| v2 != v1 && v2 != skip)
+            .collect();
+        let mut pairs = vec![];
+        for (i2, &v2) in near.iter().enumerate() {
+            for &v3 in &near[i2 + 1..] {
+                pairs.push([v2.min(v3), v2.max(v3)]);
+            }
+        }
+        for &v2 in &near {
+            for v3 in graph.positive(v2) {
+                if v3 != v1 && v3 != v2 && v3 != skip && graph[[v1, v3]].weight <= 0 {
+                    pairs.push([v2.min(v3), v2.max(v3)]);
+                }
+            }
+        }
+        pairs.sort_unstable();
+        for [v2, v3] in pairs {
+            f(self, v2, v3);
+        }
+    }
+
+    // calls `f` on the active vertices that can make a conflict triple with `v1` and `v2`
+    fn each_third<F: FnMut(&mut Self, usize)>(
+        &mut self,
+        graph: &Graph<S>,
+        v1: usize,
+        v2: usize,
+        mut f: F,
+    ) {
+        if !graph.sparse_edges() {
+            for &v3 in &graph.active {
+                if v3 != v1 && v3 != v2 {
+                    f(self, v3);
                 }
             }
+            return;
+        }
+        let mut thirds: Vec<_> = (graph.positive(v1))
+            .chain(graph.positive(v2).filter(|&v3| graph[[v1, v3]].weight <= 0))
+            .filter(|&v3| v3 != v1 && v3 != v2)
+            .collect();
+        thirds.sort_unstable();
+        for v3 in thirds {
+            f(self, v3);
         }
     }
 
-    pub fn remove_vertex(&mut self, graph: &Graph, v1: usize) {
-        if cfg!(not(feature = "incremental")) {
+    pub fn add_vertex(&mut self, graph: &Graph<S>, v1: usize) {
+        if !self.incremental {
             return;
         }
-        if cfg!(not(feature = "no-lower")) {
+        self.each_triple(graph, v1, v1, |packing, v2, v3| {
+            packing.add_triple(graph, v1, v2, v3)
+        });
+    }
+
+    pub fn remove_vertex(&mut self, graph: &Graph<S>, v1: usize) {
+        if !self.incremental {
+            return;
+        }
+        if self.lower_bound {
             for i in (0..self.triples.len()).rev() {
                 if self.triples[i].vertex(v1) {
                     let triple = self.triples.swap_remove(i);
@@ -71,40 +198,30 @@ impl Packing {
             }
         }
 
-        for (i2, v2) in graph.active.all(0) {
-            if v2 != v1 {
-                for (_, v3) in graph.active.all(i2) {
-                    if v3 != v1 {
-                        self.remove_triple_conflicts(graph, v1, v2, v3);
-                    }
-                }
-            }
-        }
+        self.each_triple(graph, v1, v1, |packing, v2, v3| {
+            packing.remove_triple_conflicts(graph, v1, v2, v3)
+        });
     }
 
-    pub fn add_vertex_pair(&mut self, graph: &Graph, v1: usize, v2: usize) {
-        if cfg!(not(feature = "incremental")) {
+    pub fn add_vertex_pair(&mut self, graph: &Graph<S>, v1: usize, v2: usize) {
+        if !self.incremental {
             return;
         }
-        for (i3, v3) in graph.active.all(0) {
-            if v1 != v3 && v2 != v3 {
-                for (_, v4) in graph.active.all(i3) {
-                    if v4 != v1 && v4 != v2 {
-                        self.add_triple(graph, v1, v3, v4);
-                        self.add_triple(graph, v2, v3, v4);
-                    }
-                }
-            }
-        }
+        self.each_triple(graph, v1, v2, |packing, v3, v4| {
+            packing.add_triple(graph, v1, v3, v4)
+        });
+        self.each_triple(graph, v2, v1, |packing, v3, v4| {
+            packing.add_triple(graph, v2, v3, v4)
+        });
 
         self.add_edge(graph, v1, v2);
     }
 
-    pub fn remove_vertex_pair(&mut self, graph: &Graph, v1: usize, v2: usize) {
-        if cfg!(not(feature = "incremental")) {
+    pub fn remove_vertex_pair(&mut self, graph: &Graph<S>, v1: usize, v2: usize) {
+        if !self.incremental {
             return;
         }
-        if cfg!(not(feature = "no-lower")) {
+        if self.lower_bound {
             for i in (0..self.triples.len()).rev() {
                 if self.triples[i].vertex(v1) || self.triples[i].vertex(v2) {
                     let triple = self.triples.swap_remove(i);
@@ -113,36 +230,30 @@ impl Packing {
             }
         }
 
-        for (i3, v3) in graph.active.all(0) {
-            if v1 != v3 && v2 != v3 {
-                for (_, v4) in graph.active.all(i3) {
-                    if v4 != v1 && v4 != v2 {
-                        self.remove_triple_conflicts(graph, v1, v3, v4);
-                        self.remove_triple_conflicts(graph, v2, v3, v4);
-                    }
-                }
-            }
-        }
+        self.each_triple(graph, v1, v2, |packing, v3, v4| {
+            packing.remove_triple_conflicts(graph, v1, v3, v4)
+        });
+        self.each_triple(graph, v2, v1, |packing, v3, v4| {
+            packing.remove_triple_conflicts(graph, v2, v3, v4)
+        });
 
         self.remove_edge_conflicts(graph, v1, v2)
     }
 
-    pub fn add_edge(&mut self, graph: &Graph, v1: usize, v2: usize) {
-        if cfg!(not(feature = "incremental")) {
+    pub fn add_edge(&mut self, graph: &Graph<S>, v1: usize, v2: usize) {
+        if !self.incremental {
             return;
         }
-        for (_, v3) in graph.active.all(0) {
-            if v1 != v3 && v2 != v3 {
-                self.add_triple(graph, v1, v2, v3);
-            }
-        }
+        self.each_third(graph, v1, v2, |packing, v3| {
+            packing.add_triple(graph, v1, v2, v3)
+        });
     }
 
-    pub fn remove_edge(&mut self, graph: &Graph, v1: usize, v2: usize) {
-        if cfg!(not(feature = "incremental")) {
+    pub fn remove_edge(&mut self, graph: &Graph<S>, v1: usize, v2: usize) {
+        if !self.incremental {
             return;
         }
-        if cfg!(not(feature = "no-lower")) {
+        if self.lower_bound {
             for i in (0..self.triples.len()).rev() {
                 if self.triples[i].edge([v1, v2]) {
                     let triple = self.triples.swap_remove(i);
@@ -154,105 +265,198 @@ impl Packing {
         self.remove_edge_conflicts(graph, v1, v2)
     }
 
-    pub fn remove_edge_conflicts(&mut self, graph: &Graph, v1: usize, v2: usize) {
-        for (_, v3) in graph.active.all(0) {
-            if v1 != v3 && v2 != v3 {
-                self.remove_triple_conflicts(graph, v1, v2, v3);
+    pub fn remove_edge_conflicts(&mut self, graph: &Graph<S>, v1: usize, v2: usize) {
+        self.each_third(graph, v1, v2, |packing, v3| {
+            packing.remove_triple_conflicts(graph, v1, v2, v3)
+        });
+    }
+
+    // scores of the pairs 13, 23 and 12 of a conflict triple
+    #[inline(always)]
+    fn conflict_scores(&self, graph: &Graph<S>, v1: usize, v2: usize, v3: usize) -> [u32; 3] {
+        match self.conflict_cost {
+            ConflictCost::Count => [1, 1, 1],
+            ConflictCost::MinWeight => [
+                min(graph[[v2, v3]].weight.abs(), graph[[v1, v2]].weight.abs()) as u32,
+                min(graph[[v1, v3]].weight.abs(), graph[[v1, v2]].weight.abs()) as u32,
+                min(graph[[v2, v3]].weight.abs(), graph[[v1, v3]].weight.abs()) as u32,
+            ]
+            .map(|score| score.min(self.max_score)),
+            ConflictCost::Deletion => {
+                let deletion = if -graph[[v1, v3]].weight >= 0 {
+                    min(graph[[v2, v3]].weight, graph[[v1, v2]].weight)
+                } else if -graph[[v2, v3]].weight >= 0 {
+                    min(graph[[v1, v3]].weight, graph[[v1, v2]].weight)
+                } else {
+                    min(graph[[v1, v3]].weight, graph[[v2, v3]].weight)
+                } as u32;
+                [deletion.min(self.max_score); 3]
             }
         }
     }
 
     #[inline(always)]
-    pub fn add_triple(&mut self, graph: &Graph, v1: usize, v2: usize, v3: usize) {
-        let e13 = -graph[[v1, v3]].weight < 0;
-        let e23 = -graph[[v2, v3]].weight < 0;
-        let e12 = -graph[[v1, v2]].weight < 0;
-        if e12 as u32 + e13 as u32 + e23 as u32 != 2 {
+    pub fn add_triple(&mut self, graph: &Graph<S>, v1: usize, v2: usize, v3: usize) {
+        if !Self::conflict(graph, v1, v2, v3) {
             return;
         }
 
-        if cfg!(feature = "alt-cost") {
-            self.edge_conflicts[[v1, v3]] +=
-                min(graph[[v2, v3]].weight.abs(), graph[[v1, v2]].weight.abs()) as u32;
-            self.edge_conflicts[[v2, v3]] +=
-                min(graph[[v1, v3]].weight.abs(), graph[[v1, v2]].weight.abs()) as u32;
-            self.edge_conflicts[[v1, v2]] +=
-                min(graph[[v2, v3]].weight.abs(), graph[[v1, v3]].weight.abs()) as u32;
-        } else if cfg!(feature = "alt-cost-2") {
-            let deletion = if -graph[[v1, v3]].weight >= 0 {
-                min(graph[[v2, v3]].weight, graph[[v1, v2]].weight)
-            } else if -graph[[v2, v3]].weight >= 0 {
-                min(graph[[v1, v3]].weight, graph[[v1, v2]].weight)
+        let [c13, c23, c12] = self.conflict_scores(graph, v1, v2, v3);
+        self.edge_conflicts[[v1, v3]] += c13;
+        self.edge_conflicts[[v2, v3]] += c23;
+        self.edge_conflicts[[v1, v2]] += c12;
+        self.queue_triple(graph, v1, v2, v3);
+
+        if !self.lower_bound {
+            return;
+        }
+        if let Some(triple) = self.pack_triple(graph, v1, v2, v3) {
+            if self.keep_triples {
+                self.triples.push(triple);
+            }
+        }
+    }
+
+    // the queue only holds the pairs of active vertices, the pairs of `vertices` follow after they
+    // are activated or deactivated
+    pub fn follow_active(&mut self, graph: &Graph<S>, vertices: &[usize]) {
+        if let Some(queue) = &mut self.queue {
+            for &v in vertices {
+                queue.follow(v, &graph.is_active);
+            }
+        }
+    }
+
+    // fixed pairs are never branched on, so they stay out of the queue
+    fn queue_pair(&mut self, graph: &Graph<S>, v1: usize, v2: usize) {
+        if let Some(queue) = &mut self.queue {
+            let key = if graph[[v1, v2]].fixed {
+                0
             } else {
-                min(graph[[v1, v3]].weight, graph[[v2, v3]].weight)
+                self.edge_conflicts[[v1, v2]]
             };
-            self.edge_conflicts[[v1, v3]] += deletion as u32;
-            self.edge_conflicts[[v2, v3]] += deletion as u32;
-            self.edge_conflicts[[v1, v2]] += deletion as u32;
-        } else {
-            self.edge_conflicts[[v1, v3]] += 1;
-            self.edge_conflicts[[v2, v3]] += 1;
-            self.edge_conflicts[[v1, v2]] += 1;
+            queue.set(v1, v2, key);
         }
+    }
 
-        if cfg!(feature = "no-lower") {
-            return;
+    #[inline(always)]
+    fn queue_triple(&mut self, graph: &Graph<S>, v1: usize, v2: usize, v3: usize) {
+        if self.queue.is_some() {
+            self.queue_pair(graph, v1, v3);
+            self.queue_pair(graph, v2, v3);
+            self.queue_pair(graph, v1, v2);
         }
+    }
 
-        if self.edge_cost[[v1, v2]] == graph[[v1, v2]].weight.abs() as u32
-            || self.edge_cost[[v1, v3]] == graph[[v1, v3]].weight.abs() as u32
-            || self.edge_cost[[v2, v3]] == graph[[v2, v3]].weight.abs() as u32
-        {
-            return;
+    // whether exactly two of the pairs are edges
+    fn conflict(graph: &Graph<S>, v1: usize, v2: usize, v3: usize) -> bool {
+        let e13 = -graph[[v1, v3]].weight < 0;
+        let e23 = -graph[[v2, v3]].weight < 0;
+        let e12 = -graph[[v1, v2]].weight < 0;
+        e12 as u32 + e13 as u32 + e23 as u32 == 2
+    }
+
+    // how much of the triples a pair can take before it is used up. deletion only cuts the edges of
+    // a triple and completion only merges its non-edge, the other pairs are never used up
+    fn capacity(&self, edge: Edge) -> u32 {
+        let editable = match self.variant {
+            ProblemVariant::Editing => true,
+            ProblemVariant::Deletion => !edge.fixed && edge.weight > 0,
+            ProblemVariant::Completion => !edge.fixed && edge.weight <= 0,
+        };
+        if editable {
+            edge.weight.unsigned_abs()
+        } else {
+            u32::MAX
+        }
+    }
+
+    // claim what is left of the pairs of a conflict triple for the lower bound
+    fn pack_triple(&mut self, graph: &Graph<S>, v1: usize, v2: usize, v3: usize) -> Option<Triple> {
+        let c12 = self.capacity(graph[[v1, v2]]);
+        let c13 = self.capacity(graph[[v1, v3]]);
+        let c23 = self.capacity(graph[[v2, v3]]);
+        // no edit resolves the triple, the solver finds out that there is no solution
+        if c12 == u32::MAX && c13 == u32::MAX && c23 == u32::MAX {
+            return None;
         }
 
         let cost = min(
-            graph[[v1, v2]].weight.abs() as u32 - self.edge_cost[[v1, v2]],
+            c12 - self.edge_cost[[v1, v2]],
             min(
-                graph[[v1, v3]].weight.abs() as u32 - self.edge_cost[[v1, v3]],
-                graph[[v2, v3]].weight.abs() as u32 - self.edge_cost[[v2, v3]],
+                c13 - self.edge_cost[[v1, v3]],
+                c23 - self.edge_cost[[v2, v3]],
             ),
         );
+        if cost == 0 {
+            return None;
+        }
         self.edge_cost[[v1, v3]] += cost;
         self.edge_cost[[v2, v3]] += cost;
         self.edge_cost[[v1, v2]] += cost;
-        if cfg!(feature = "incremental") {
-            self.triples.push(Triple::new([v1, v2, v3], cost));
-        }
-        self.lower += cost;
+        self.lower = self.lower.saturating_add(cost);
+        Some(Triple::new([v1, v2, v3], cost))
     }
 
-    pub fn remove_triple_conflicts(&mut self, graph: &Graph, v1: usize, v2: usize, v3: usize) {
-        let e13 = -graph[[v1, v3]].weight < 0;
-        let e23 = -graph[[v2, v3]].weight < 0;
-        let e12 = -graph[[v1, v2]].weight < 0;
-        if e12 as u32 + e13 as u32 + e23 as u32 != 2 {
+    // 1-for-2 swaps: a triple gives up its pairs to the conflict triples that share one of them,
+    // which stay when they are worth more. repeats until no triple can be swapped, or for
+    // `MAX_PASSES` passes. the triples of inactive vertices belong to other components and stay as
+    // they are
+    pub fn improve(&mut self, graph: &Graph<S>) {
+        if !self.lower_bound {
             return;
         }
+        for _ in 0..MAX_PASSES {
+            let mut improved = false;
+            let mut i = 0;
+            while i < self.triples.len() {
+                let triple = self.triples[i];
+                if !triple.vertices.iter().all(|&v| graph.is_active[v]) {
+                    i += 1;
+                    continue;
+                }
+                self.remove_triple_cost(triple);
+                let [v1, v2, v3] = triple.vertices;
+                let mut packed = vec![];
+                for [v1, v2] in [[v1, v2], [v1, v3], [v2, v3]] {
+                    self.each_third(graph, v1, v2, |packing, v4| {
+                        if !triple.vertex(v4) && Self::conflict(graph, v1, v2, v4) {
+                            packed.extend(packing.pack_triple(graph, v1, v2, v4));
+                        }
+                    });
+                }
 
-        if cfg!(feature = "alt-cost") {
-            self.edge_conflicts[[v1, v3]] -=
-                min(graph[[v2, v3]].weight.abs(), graph[[v1, v2]].weight.abs()) as u32;
-            self.edge_conflicts[[v2, v3]] -=
-                min(graph[[v1, v3]].weight.abs(), graph[[v1, v2]].weight.abs()) as u32;
-            self.edge_conflicts[[v1, v2]] -=
-                min(graph[[v2, v3]].weight.abs(), graph[[v1, v3]].weight.abs()) as u32;
-        } else if cfg!(feature = "alt-cost-2") {
-            let deletion = if -graph[[v1, v3]].weight >= 0 {
-                min(graph[[v2, v3]].weight, graph[[v1, v2]].weight)
-            } else if -graph[[v2, v3]].weight >= 0 {
-                min(graph[[v1, v3]].weight, graph[[v1, v2]].weight)
-            } else {
-                min(graph[[v1, v3]].weight, graph[[v2, v3]].weight)
-            };
-            self.edge_conflicts[[v1, v3]] -= deletion as u32;
-            self.edge_conflicts[[v2, v3]] -= deletion as u32;
-            self.edge_conflicts[[v1, v2]] -= deletion as u32;
-        } else {
-            self.edge_conflicts[[v1, v3]] -= 1;
-            self.edge_conflicts[[v2, v3]] -= 1;
-            self.edge_conflicts[[v1, v2]] -= 1;
+                if packed.iter().map(|triple| triple.cost).sum::<u32>() > triple.cost {
+                    self.triples.swap_remove(i);
+                    self.triples.extend(packed);
+                    improved = true;
+                    continue;
+                }
+                for triple in packed {
+                    self.remove_triple_cost(triple);
+                }
+                // it gets back at least what it held
+                let repacked = self.pack_triple(graph, v1, v2, v3).unwrap();
+                improved |= repacked.cost > triple.cost;
+                self.triples[i] = repacked;
+                i += 1;
+            }
+            if !improved {
+                break;
+            }
+        }
+    }
+
+    pub fn remove_triple_conflicts(&mut self, graph: &Graph<S>, v1: usize, v2: usize, v3: usize) {
+        if !Self::conflict(graph, v1, v2, v3) {
+            return;
         }
+
+        let [c13, c23, c12] = self.conflict_scores(graph, v1, v2, v3);
+        self.edge_conflicts[[v1, v3]] -= c13;
+        self.edge_conflicts[[v2, v3]] -= c23;
+        self.edge_conflicts[[v1, v2]] -= c12;
+        self.queue_triple(graph, v1, v2, v3);
     }
 
     pub fn remove_triple_cost(&mut self, triple: Triple) {
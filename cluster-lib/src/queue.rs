@@ -0,0 +1,95 @@
+use crate::{
+    graph::Edge,
+    matrix::{Matrix, Storage},
+};
+
+// the position of a hidden pair
+const HIDDEN: u32 = u32::MAX;
+
+// the pairs with a positive key in buckets by their key, so a pair with the highest key is found
+// without looking at the others. keys only change by small steps, so the buckets stay few. the
+// pairs of inactive vertices are hidden, they keep their key outside of the buckets
+#[derive(Clone)]
+pub struct BucketQueue<S: Storage<Edge> = Matrix<Edge>> {
+    buckets: Vec<Vec<[usize; 2]>>,
+    // the key of every pair and its position in its bucket, `HIDDEN` while it is hidden
+    entries: S::Like<(u32, u32)>,
+    // the highest key of a pair, 0 without any
+    max: usize,
+}
+
+impl<S: Storage<Edge>> BucketQueue<S> {
+    pub fn new(like: &S, len: usize) -> Self {
+        Self {
+            buckets: vec![vec![]],
+            entries: like.like((0, 0), len),
+            max: 0,
+        }
+    }
+
+    pub fn set(&mut self, v1: usize, v2: usize, key: u32) {
+        let pair = [v1.min(v2), v1.max(v2)];
+        let (old, position) = self.entries[pair];
+        if old == key {
+            return;
+        }
+        if position == HIDDEN {
+            self.entries[pair].0 = key;
+            return;
+        }
+        self.take(pair, old, position);
+        self.put(pair, key);
+    }
+
+    // take the pairs of `v` with an inactive vertex out of the buckets and put the others back,
+    // after `v` or some of the vertices it has pairs with are activated or deactivated
+    pub fn follow(&mut self, v: usize, is_active: &[bool]) {
+        let others: Vec<_> = self.entries.neighbours(v).collect();
+        for v2 in others {
+            let pair = [v.min(v2), v.max(v2)];
+            let (key, position) = self.entries[pair];
+            match (is_active[v] && is_active[v2], position == HIDDEN) {
+                (false, false) if key > 0 => {
+                    self.take(pair, key, position);
+                    self.entries[pair] = (key, HIDDEN);
+                }
+                (true, true) => self.put(pair, key),
+                _ => {}
+            }
+        }
+    }
+
+    // a pair with the highest key
+    pub fn first(&self) -> Option<[usize; 2]> {
+        self.buckets[self.max].first().copied()
+    }
+
+    fn take(&mut self, pair: [usize; 2], key: u32, position: u32) {
+        if key == 0 {
+            return;
+        }
+        let bucket = &mut self.buckets[key as usize];
+        bucket.swap_remove(position as usize);
+        if let Some(&moved) = bucket.get(position as usize) {
+            self.entries[moved].1 = position;
+        }
+        self.entries[pair] = (0, 0);
+        while self.max > 0 && self.buckets[self.max].is_empty() {
+            self.max -= 1;
+        }
+    }
+
+    fn put(&mut self, pair: [usize; 2], key: u32) {
+        let key = key as usize;
+        if key == 0 {
+            self.entries[pair] = (0, 0);
+            return;
+        }
+        if self.buckets.len() <= key {
+            self.buckets.resize(key + 1, vec![]);
+        }
+        self.entries[pair] = (key as u32, self.buckets[key].len() as u32);
+        self.buckets[key].push(pair);
+        self.max = self.max.max(key);
+    }
+}
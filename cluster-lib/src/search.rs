@@ -1,145 +1,837 @@
-use std::cmp::max;
+use std::{
+    cmp::{max, min},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
 
 use crate::{
-    branch::EdgeMod, component::Components, disk::finish_solve, graph::Graph, packing::Packing,
+    branch::{rule, BranchingRule, EdgeMod},
+    component::Components,
+    config::{ClusterCount, Improvement, LpBound, ProblemVariant, SolverConfig},
+    count::{fit, fits},
+    disk::solution_cost,
+    graph::{Edge, Graph},
+    heuristic::{closure, clustering_cost, heuristic, upper_bound},
+    lp::Relaxation,
+    matrix::{Matrix, Storage},
+    packing::Packing,
+    stats::SolverStats,
+    verify::Violation,
 };
 
 #[derive(Clone)]
-pub struct Solver {
-    pub graph: Graph,
-    pub packing: Packing,
+pub struct Solver<S: Storage<Edge> = Matrix<Edge>> {
+    pub graph: Graph<S>,
+    pub packing: Packing<S>,
     pub components: Components,
     pub upper: u32,
+    // only records the clustering, through `root`
     pub best: Graph,
+    pub config: SolverConfig,
+    pub deadline: Option<Instant>,
+    pub cancel: Option<Arc<AtomicBool>>,
+    pub optimal: bool,
+    // the search is done and no clustering fits the constraints, the variant and the bounds, so
+    // `upper` is `u32::MAX` and `best` is no solution
+    pub infeasible: bool,
+    pub stats: SolverStats,
+    // picks the pair to branch on, from `config.branching` unless it is replaced
+    pub rule: Box<dyn BranchingRule<S>>,
+    // a lower bound on the input graph, the packing or the lp relaxation if it is stronger
+    lower: u32,
+    stopped: bool,
+    started: Instant,
+    // what has been taken from `upper` on the current path, to report the total cost at a leaf
+    spent: u32,
+    pub(crate) depth: usize,
+    // the lp relaxation of the current node and the ones above it, by depth
+    pub(crate) relaxations: Vec<Option<Relaxation>>,
+    // first components on the current path, their leaves are not complete solutions
+    pending: usize,
+    shared: Option<Arc<Shared>>,
+    // above this depth both branches of a node are offered to the thread pool
+    split: usize,
+}
+
+// what is left to do in the search. the steps are kept on a stack instead of recursing, so the
+// depth of the search is only limited by memory. `enumerate` takes the same steps, without the
+// ones for components
+pub(crate) enum Step {
+    Graph,
+    Components,
+    Merge(usize, usize),
+    Cut(usize, usize),
+    // come back up from below a merge or cut that cost `cost`
+    UnMerge {
+        v1: usize,
+        v2: usize,
+        vv: usize,
+        cost: u32,
+    },
+    UnCut {
+        v1: usize,
+        v2: usize,
+        edge: Edge,
+        cost: u32,
+    },
+    // the first component is searched with `cost_other` set aside for the others
+    FirstComponent {
+        upper_both: u32,
+        cost_other: u32,
+        best: Vec<Option<usize>>,
+        other_count: usize,
+    },
+    // the other components are searched after the first one took `cost`
+    OtherComponents {
+        upper_both: u32,
+        cost: u32,
+        best: Vec<Option<usize>>,
+        count: usize,
+    },
+}
+
+// the state of a parallel search, shared by all solvers in the pool
+struct Shared {
+    upper: AtomicU32,
+    best: Mutex<Option<Vec<Option<usize>>>>,
+    // what the solvers of stolen branches did
+    stats: Mutex<SolverStats>,
+    stopped: AtomicBool,
 }
 
-impl Solver {
-    pub fn new(graph: Graph) -> Self {
+impl<S: Storage<Edge>> Solver<S> {
+    pub fn new(mut graph: Graph<S>, config: SolverConfig) -> Self {
+        graph.restrict(config.variant);
         let len = graph.vertex_merged.len();
-        let mut packing = Packing::new(len);
+        let mut packing = Packing::new(&graph, config);
         packing.pack(&graph);
-        Self {
-            graph: graph.clone(),
+        if config.improve_packing != Improvement::Off {
+            packing.improve(&graph);
+        }
+        let mut root_lower = packing.lower;
+        let mut relaxation = None;
+        if config.lp_bound != LpBound::Off && config.variant != ProblemVariant::Completion {
+            relaxation = Relaxation::new(&graph, &config, u32::MAX);
+            root_lower = max(root_lower, relaxation.as_ref().map_or(0, Relaxation::lower));
+        }
+        let mut solver = Self {
+            upper: graph.edge_weight(),
+            best: Graph::record(&graph),
+            graph,
             packing,
             components: Components::new(len),
-            upper: graph.edge_count(),
-            best: graph,
+            config,
+            deadline: None,
+            cancel: None,
+            optimal: false,
+            infeasible: false,
+            stats: SolverStats {
+                root_lower,
+                ..SolverStats::default()
+            },
+            rule: rule(config.branching),
+            lower: root_lower,
+            stopped: false,
+            started: Instant::now(),
+            spent: 0,
+            depth: 0,
+            relaxations: vec![relaxation],
+            pending: 0,
+            shared: None,
+            split: 0,
+        };
+
+        if config.variant == ProblemVariant::Completion {
+            // nothing is left to search, a forbidden pair inside a component makes it infeasible
+            let clusters = closure(&solver.graph);
+            solver.store_best(&clusters);
+            solver.upper = upper_bound(&solver.graph, &clusters);
+        } else if config.cluster_count != ClusterCount::Any || config.max_cluster_size.is_some() {
+            // deleting every edge rarely has the right number of clusters, so the start is fitted
+            // to the bounds. when it can not be, or a forbidden pair is inside a cluster, there is
+            // no upper bound
+            let mut clusters: Vec<_> = (0..len).collect();
+            if config.heuristic_rounds > 0 {
+                clusters = heuristic(&solver.graph, config.heuristic_rounds).0;
+            }
+            let fits = fit(&solver.graph, &mut clusters, &config);
+            solver.store_best(&clusters);
+            solver.upper = if fits {
+                upper_bound(&solver.graph, &clusters)
+            } else {
+                u32::MAX
+            };
+        } else if config.heuristic_rounds > 0 {
+            let (clusters, _) = heuristic(&solver.graph, config.heuristic_rounds);
+            let cost = upper_bound(&solver.graph, &clusters);
+            if cost < solver.upper {
+                solver.store_best(&clusters);
+                solver.upper = cost;
+            }
+        }
+        solver
+    }
+
+    pub fn store_best(&mut self, clusters: &[usize]) {
+        self.best.store_clusters(&self.graph, clusters);
+    }
+
+    // start from a clustering of the original vertices, labelled by any cluster ids, so the search
+    // prunes with its cost right away. it has to keep merged vertices together, forbidden pairs
+    // apart and fit the bounds of the config. a cheaper start from the heuristic is kept
+    pub fn with_initial_solution(mut self, clusters: &[usize]) -> Result<Self, Violation> {
+        self.start_from(clusters)?;
+        Ok(self)
+    }
+
+    // like `with_initial_solution`, but the solver is left as it is when the start is violated
+    pub fn start_from(&mut self, clusters: &[usize]) -> Result<(), Violation> {
+        let labels = self.graph.root_labels(clusters, self.config.variant)?;
+        if !fits(&self.graph, &labels, &self.config) {
+            return Err(Violation::Bounds);
+        }
+
+        let cost = clustering_cost(&self.graph, &labels);
+        if cost < self.upper as u64 {
+            self.store_best(&labels);
+            self.upper = cost as u32;
+        }
+        Ok(())
+    }
+
+    // set the weights of pairs of active vertices between searches, the packing follows them like
+    // it follows a cut. the next search starts from `best`, still a clustering of the same vertices
+    pub fn edit(&mut self, edits: &[(usize, usize, i32)]) {
+        for &(v1, v2, weight) in edits {
+            let mut edge = Edge::new(weight);
+            if self.config.variant == ProblemVariant::Deletion && weight <= 0 {
+                edge = Edge::none(); // like `restrict`
+            }
+            self.packing.remove_edge(&self.graph, v1, v2);
+            self.graph[[v1, v2]] = edge;
+            self.packing.add_edge(&self.graph, v1, v2);
+        }
+        if !self.config.incremental {
+            self.packing.pack(&self.graph);
+        }
+        if self.config.improve_packing != Improvement::Off {
+            self.packing.improve(&self.graph);
+        }
+        self.lower = self.packing.lower;
+        self.relaxations = vec![None];
+        if self.config.lp_bound != LpBound::Off && self.config.variant != ProblemVariant::Completion
+        {
+            self.relaxations[0] = Relaxation::new(&self.graph, &self.config, u32::MAX);
+            let relaxation = self.relaxations[0].as_ref();
+            self.lower = max(self.lower, relaxation.map_or(0, Relaxation::lower));
+        }
+        self.stats.root_lower = self.lower;
+
+        let mut labels: Vec<_> = (0..self.graph.vertex_merged.len()).collect();
+        for &v in &self.graph.active {
+            labels[v] = self.best.root(v);
+        }
+        if self.config.variant == ProblemVariant::Completion {
+            // the closure is the only solution, as in `new`
+            labels = closure(&self.graph);
+            self.store_best(&labels);
+        }
+        self.upper = if fits(&self.graph, &labels, &self.config) {
+            upper_bound(&self.graph, &labels)
+        } else {
+            u32::MAX
+        };
+        self.optimal = false;
+        self.infeasible = false;
+    }
+
+    // search until the deadline or cancellation, `best` and `upper` hold the best solution found
+    pub fn solve(&mut self) {
+        self.stopped = false;
+        if self.lower >= self.upper {
+            // the start is already optimal
+        } else if self.config.deepening && self.upper < u32::MAX {
+            // without any solution deepening would not know when to stop
+            let mut k = self.lower;
+            while k < self.upper && self.decide(k) == Some(false) {
+                k += 1;
+            }
+        } else {
+            self.search();
+        }
+        self.infeasible = !self.stopped && self.upper == u32::MAX;
+        self.optimal = !self.stopped && !self.infeasible;
+        // without any solution `best` holds no clustering to cost
+        if self.stopped && self.upper != u32::MAX {
+            self.upper = solution_cost(&self.graph, &self.best);
+        }
+    }
+
+    // whether there is a solution of cost at most `k`, in which case `best` and `upper` hold one.
+    // `None` when the search is stopped before it can tell
+    pub fn decide(&mut self, k: u32) -> Option<bool> {
+        if k >= self.upper {
+            return Some(true);
+        }
+        if k < self.lower {
+            return Some(false);
+        }
+        self.stopped = false;
+        if !self.config.incremental {
+            self.packing.pack(&self.graph); // the previous search leaves a stale packing behind
+        }
+        let upper = self.upper;
+        self.upper = k + 1;
+        self.search();
+
+        if self.stopped {
+            if self.upper > k {
+                self.upper = upper;
+            }
+            // without any solution `best` holds no clustering to cost
+            if self.upper != u32::MAX {
+                self.upper = solution_cost(&self.graph, &self.best);
+            }
+            return if self.upper <= k { Some(true) } else { None };
+        }
+        if self.upper <= k {
+            Some(true)
+        } else {
+            self.upper = upper;
+            Some(false)
+        }
+    }
+
+    fn search(&mut self) {
+        if self.config.variant == ProblemVariant::Completion {
+            // `new` already found the only solution
+        } else if self.config.threads > 1 {
+            self.search_parallel()
+        } else {
+            self.search_components()
         }
     }
 
+    // branch on a thread pool until the subtrees are small enough, then search them directly.
+    // all solvers prune with the best upper bound found by any of them
+    fn search_parallel(&mut self) {
+        let shared = Arc::new(Shared {
+            upper: AtomicU32::new(self.upper),
+            best: Mutex::new(None),
+            stats: Mutex::new(SolverStats::default()),
+            stopped: AtomicBool::new(false),
+        });
+        self.shared = Some(Arc::clone(&shared));
+        // about 8 subtrees per thread
+        self.split = (8 * self.config.threads)
+            .next_power_of_two()
+            .trailing_zeros() as usize;
+        // a branch that is stolen by another thread starts from here
+        let root = self.clone();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.config.threads)
+            .build()
+            .unwrap();
+        pool.install(|| self.fork(&root, &mut vec![]));
+        self.split = 0;
+
+        self.stats.add(&shared.stats.lock().unwrap());
+        self.stats.improvements.sort_by_key(|&(time, _)| time);
+        self.stopped |= shared.stopped.load(Ordering::SeqCst);
+
+        self.shared = None;
+        if let Some(best) = shared.best.lock().unwrap().take() {
+            self.best.vertex_merged = best;
+        }
+        self.upper = shared.upper.load(Ordering::SeqCst);
+    }
+
+    // search the node below `root` along `path`. above the split depth the second branch can be
+    // stolen by another thread, which then clones `root` and takes the path to it. otherwise this
+    // solver searches it after the first one
+    fn fork(&mut self, root: &Self, path: &mut Vec<EdgeMod>) {
+        if self.depth == self.split {
+            return self.search_components();
+        }
+        let (first, second) = match self.node() {
+            Some(EdgeMod::Merge(v1, v2)) => (EdgeMod::Merge(v1, v2), EdgeMod::Cut(v1, v2)),
+            Some(EdgeMod::Cut(v1, v2)) => (EdgeMod::Cut(v1, v2), EdgeMod::Merge(v1, v2)),
+            _ => return,
+        };
+        // only the path is copied for the second branch, the solver is cloned when it is stolen
+        let mut stolen_path = path.clone();
+        let (_, stolen) = rayon::join_context(
+            |_| self.fork_branch(first, root, path),
+            |context| {
+                if !context.migrated() {
+                    return false;
+                }
+                let mut task = Self::replay(root, &stolen_path);
+                task.fork_branch(second, root, &mut stolen_path);
+                let shared = task.shared.as_ref().unwrap();
+                shared.stats.lock().unwrap().add(&task.stats);
+                shared.stopped.fetch_or(task.stopped, Ordering::SeqCst);
+                true
+            },
+        );
+        if !stolen {
+            self.fork_branch(second, root, path);
+        }
+    }
+
+    // take the branch and `fork` below it, unless it is pruned
+    fn fork_branch(&mut self, edge: EdgeMod, root: &Self, path: &mut Vec<EdgeMod>) {
+        path.push(edge);
+        match edge {
+            EdgeMod::Merge(v1, v2) => {
+                if let Some((vv, cost)) = self.enter_merge(v1, v2) {
+                    self.descend(cost);
+                    self.fork(root, path);
+                    self.ascend(cost);
+                    self.upper += cost;
+                    self.un_merge(v1, v2, vv);
+                }
+            }
+            EdgeMod::Cut(v1, v2) => {
+                if let Some((old, cost)) = self.enter_cut(v1, v2) {
+                    self.descend(cost);
+                    self.fork(root, path);
+                    self.ascend(cost);
+                    self.upper += cost;
+                    self.un_cut(v1, v2, old);
+                }
+            }
+            EdgeMod::Nothing => {}
+        }
+        path.pop();
+    }
+
+    // a clone of `root` at the end of `path`, with statistics of its own
+    fn replay(root: &Self, path: &[EdgeMod]) -> Self {
+        let mut task = root.clone();
+        for &edge in path {
+            let cost = match edge {
+                EdgeMod::Merge(v1, v2) => task.merge(v1, v2).1,
+                EdgeMod::Cut(v1, v2) => task.cut(v1, v2).1,
+                EdgeMod::Nothing => 0,
+            };
+            task.upper -= cost;
+            task.descend(cost);
+        }
+        task.stats = SolverStats::default();
+        // the relaxations below the root are of other nodes
+        task.relaxations.truncate(1);
+        task
+    }
+
+    // lower `upper` to what is left of the shared upper bound, returns false if the current
+    // branch can not improve it anymore
+    fn sync_upper(&mut self) -> bool {
+        if let Some(shared) = &self.shared {
+            if self.pending == 0 {
+                let budget = shared
+                    .upper
+                    .load(Ordering::Relaxed)
+                    .saturating_sub(self.spent);
+                if self.packing.lower >= budget {
+                    return false;
+                }
+                self.upper = min(self.upper, budget);
+            }
+        }
+        true
+    }
+
+    // record a complete solution of cost `upper`, in a parallel search it is offered to the
+    // other solvers and only counts if it beats all of them
+    fn improved(&mut self, upper: u32) {
+        // holding the lock keeps the timestamps of all solvers in the order of their costs
+        let mut lock = None;
+        if let Some(shared) = &self.shared {
+            let mut best = shared.best.lock().unwrap();
+            if upper >= shared.upper.load(Ordering::SeqCst) {
+                return;
+            }
+            shared.upper.store(upper, Ordering::SeqCst);
+            *best = Some(self.best.vertex_merged.clone());
+            lock = Some(best);
+        }
+        let time = self.started.elapsed();
+        self.stats.improvements.push((time, upper));
+        drop(lock);
+    }
+
+    pub fn should_stop(&mut self) -> bool {
+        if !self.stopped {
+            let cancelled = matches!(&self.cancel, Some(cancel) if cancel.load(Ordering::Relaxed));
+            let expired = matches!(self.deadline, Some(deadline) if Instant::now() >= deadline);
+            self.stopped = cancelled || expired;
+        }
+        self.stopped
+    }
+
     pub fn search_components(&mut self) {
-        let other_count = self.components.isolate_component(&mut self.graph);
+        self.run(Step::Components);
+    }
+
+    pub fn search_merge(&mut self, v1: usize, v2: usize) {
+        self.run(Step::Merge(v1, v2));
+    }
+
+    pub fn search_cut(&mut self, v1: usize, v2: usize) {
+        self.run(Step::Cut(v1, v2));
+    }
+
+    pub fn search_graph(&mut self) {
+        self.run(Step::Graph);
+    }
+
+    // take steps until none is left, each one pushes what has to follow it
+    fn run(&mut self, step: Step) {
+        let mut stack = vec![step];
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Graph => self.step_graph(&mut stack),
+                Step::Components => self.step_components(&mut stack),
+                Step::Merge(v1, v2) => self.step_merge(v1, v2, &mut stack),
+                Step::Cut(v1, v2) => self.step_cut(v1, v2, &mut stack),
+                Step::UnMerge { v1, v2, vv, cost } => {
+                    self.ascend(cost);
+                    self.upper += cost;
+                    self.un_merge(v1, v2, vv);
+                }
+                Step::UnCut { v1, v2, edge, cost } => {
+                    self.ascend(cost);
+                    self.upper += cost;
+                    self.un_cut(v1, v2, edge);
+                }
+                Step::FirstComponent {
+                    upper_both,
+                    cost_other,
+                    best,
+                    other_count,
+                } => {
+                    self.step_first_component(upper_both, cost_other, best, other_count, &mut stack)
+                }
+                Step::OtherComponents {
+                    upper_both,
+                    cost,
+                    best,
+                    count,
+                } => {
+                    self.spent -= cost;
+                    self.upper += cost;
+
+                    if self.upper == upper_both {
+                        self.best.vertex_merged = best;
+                    }
+
+                    self.all_components(count);
+                }
+            }
+        }
+    }
+
+    fn step_components(&mut self, stack: &mut Vec<Step>) {
+        if self.config.cluster_count != ClusterCount::Any {
+            return stack.push(Step::Graph);
+        }
+        let other_count = self.isolate_component();
         if other_count == 0 {
-            return self.search_graph();
+            return stack.push(Step::Graph);
         }
+        self.stats.component_splits += 1;
 
         let upper_both = self.upper;
         let mut cost_other = 0;
 
-        if cfg!(not(feature = "incremental")) {
-            let lower_both = self.packing.lower;
+        if !self.config.incremental {
+            // the packing is greedy, so what the first component takes from the packing of both is
+            // no bound for the others. they are packed on their own instead
+            let first = self.other_component(other_count);
+            self.packing.pack(&self.graph);
+            cost_other = self.packing.lower;
+            self.other_component(first);
             self.packing.pack(&self.graph);
-            cost_other = lower_both - self.packing.lower;
         }
+        if self.packing.lower.saturating_add(cost_other) >= upper_both {
+            self.stats.pruned_components += 1;
+            self.all_components(other_count);
+            return;
+        }
+
+        // the first component only improves `best` if the other components fit in the budget too
+        let best = self.best.vertex_merged.clone();
 
         self.upper -= cost_other;
-        self.search_graph();
+        self.spent += cost_other;
+        self.pending += 1;
+        stack.push(Step::FirstComponent {
+            upper_both,
+            cost_other,
+            best,
+            other_count,
+        });
+        stack.push(Step::Graph);
+    }
+
+    fn step_first_component(
+        &mut self,
+        upper_both: u32,
+        cost_other: u32,
+        best: Vec<Option<usize>>,
+        other_count: usize,
+        stack: &mut Vec<Step>,
+    ) {
+        self.pending -= 1;
+        self.spent -= cost_other;
         self.upper += cost_other;
 
         if self.upper == upper_both {
-            self.components
-                .all_components(&mut self.graph.active, other_count);
+            self.stats.pruned_components += 1;
+            self.all_components(other_count);
             return;
         }
         assert!(upper_both > self.upper);
 
-        let count = self
-            .components
-            .other_component(&mut self.graph.active, other_count);
+        let count = self.other_component(other_count);
 
-        let mut cost = self.upper - self.packing.lower; // how much the component costs on top of the lower bound
-
-        if cfg!(not(feature = "incremental")) {
-            cost = self.upper - cost_other;
+        let cost = if self.config.incremental {
+            self.upper - self.packing.lower // how much the component costs on top of the lower bound
+        } else {
             self.packing.pack(&self.graph); // i do not know why it has to be recalculated here
                                             // self.packing.lower = cost_other;
-        }
+            self.upper - cost_other
+        };
 
         self.upper = upper_both; // the upper bound stays the same because we have not yet solved all components
 
-        let old_len = self.graph.len;
-        self.graph.len = self.best.len; // we need to add the solutions
         self.upper -= cost; // remove the cost of the first component
-        self.search_components();
-        self.upper += cost;
-        self.graph.len = old_len; // from here we will overwrite solutions again
+        self.spent += cost;
+        stack.push(Step::OtherComponents {
+            upper_both,
+            cost,
+            best,
+            count,
+        });
+        stack.push(Step::Components);
+    }
 
-        self.components
-            .all_components(&mut self.graph.active, count);
+    fn step_merge(&mut self, v1: usize, v2: usize, stack: &mut Vec<Step>) {
+        if let Some((vv, cost)) = self.enter_merge(v1, v2) {
+            stack.push(Step::UnMerge { v1, v2, vv, cost });
+            self.descend(cost);
+            stack.push(self.next_step());
+        }
     }
 
-    pub fn search_merge(&mut self, v1: usize, v2: usize) {
-        self.packing.remove_vertex_pair(&self.graph, v1, v2);
-        let (vv, cost) = self.graph.merge(v1, v2);
-        self.packing.add_vertex(&self.graph, vv);
+    fn step_cut(&mut self, v1: usize, v2: usize, stack: &mut Vec<Step>) {
+        if let Some((edge, cost)) = self.enter_cut(v1, v2) {
+            stack.push(Step::UnCut { v1, v2, edge, cost });
+            self.descend(cost);
+            stack.push(self.next_step());
+        }
+    }
 
-        if cfg!(not(feature = "incremental")) {
-            self.packing.pack(&self.graph)
+    // merge the pair if the bounds leave room for it and take its cost from `upper`
+    fn enter_merge(&mut self, v1: usize, v2: usize) -> Option<(usize, u32)> {
+        if !self.fits_size(v1, v2) {
+            self.stats.pruned_merge += 1;
+            return None;
         }
-        if self.packing.lower + (cost as u32) < self.upper {
+        let (vv, cost) = self.merge(v1, v2);
+        let lower = self.packing.lower.saturating_add(cost);
+        if lower < self.upper && self.lp_fits(EdgeMod::Merge(v1, v2), cost) {
             self.upper -= cost;
-            if cfg!(feature = "branch-comp") {
-                self.search_components()
-            } else {
-                self.search_graph();
-            }
-            self.upper += cost;
+            Some((vv, cost))
+        } else {
+            self.stats.pruned_merge += 1;
+            self.un_merge(v1, v2, vv);
+            None
         }
-        self.packing.remove_vertex(&self.graph, vv);
-        self.graph.un_merge(v1, v2, vv);
-        self.packing.add_vertex_pair(&self.graph, v1, v2);
     }
 
-    pub fn search_cut(&mut self, v1: usize, v2: usize) {
-        self.packing.remove_edge(&self.graph, v1, v2);
-        let edge = self.graph.cut(v1, v2);
-        self.packing.add_edge(&self.graph, v1, v2);
-        let cost = max(0, edge.weight) as u32;
+    // cut the pair if the bounds leave room for it and take its cost from `upper`
+    fn enter_cut(&mut self, v1: usize, v2: usize) -> Option<(Edge, u32)> {
+        let (edge, cost) = self.cut(v1, v2);
+        let lower = self.packing.lower.saturating_add(cost);
+        if lower < self.upper && self.lp_fits(EdgeMod::Cut(v1, v2), cost) {
+            self.upper -= cost;
+            Some((edge, cost))
+        } else {
+            self.stats.pruned_cut += 1;
+            self.un_cut(v1, v2, edge);
+            None
+        }
+    }
+
+    // merge the vertices and update the packing, returns the merged vertex and the cost
+    pub fn merge(&mut self, v1: usize, v2: usize) -> (usize, u32) {
+        self.stats.merges += 1;
+        self.time_packing(|solver| {
+            solver.packing.remove_vertex_pair(&solver.graph, v1, v2);
+            let (vv, cost) = solver.graph.merge(v1, v2);
+            solver.packing.add_vertex(&solver.graph, vv);
+            solver.packing.follow_active(&solver.graph, &[v1, v2]);
 
-        if cfg!(not(feature = "incremental")) {
-            self.packing.pack(&self.graph)
+            if !solver.config.incremental {
+                solver.packing.pack(&solver.graph)
+            }
+            (vv, cost)
+        })
+    }
+
+    pub fn un_merge(&mut self, v1: usize, v2: usize, vv: usize) {
+        self.time_packing(|solver| {
+            solver.packing.remove_vertex(&solver.graph, vv);
+            solver.graph.un_merge(v1, v2, vv);
+            solver.packing.add_vertex_pair(&solver.graph, v1, v2);
+            solver.packing.follow_active(&solver.graph, &[v1, v2, vv]);
+        })
+    }
+
+    // forbid the edge and update the packing, returns the old edge and the cost
+    pub fn cut(&mut self, v1: usize, v2: usize) -> (Edge, u32) {
+        self.stats.cuts += 1;
+        self.time_packing(|solver| {
+            solver.packing.remove_edge(&solver.graph, v1, v2);
+            let edge = solver.graph.cut(v1, v2);
+            solver.packing.add_edge(&solver.graph, v1, v2);
+            let cost = max(0, edge.weight) as u32;
+
+            if !solver.config.incremental {
+                solver.packing.pack(&solver.graph)
+            }
+            (edge, cost)
+        })
+    }
+
+    pub fn un_cut(&mut self, v1: usize, v2: usize, edge: Edge) {
+        self.time_packing(|solver| {
+            solver.packing.remove_edge(&solver.graph, v1, v2);
+            solver.graph.un_cut(v1, v2, edge);
+            solver.packing.add_edge(&solver.graph, v1, v2);
+        })
+    }
+
+    // set the components other than the one of the first active vertex aside, returns how many
+    // vertices they have. the queue of the packing follows the active vertices here and below
+    pub(crate) fn isolate_component(&mut self) -> usize {
+        let count = self.components.isolate_component(&mut self.graph);
+        (self.packing).follow_active(&self.graph, self.components.aside(count));
+        count
+    }
+
+    // swap the active vertices with the last `count` that were set aside, returns how many were
+    // active
+    pub(crate) fn other_component(&mut self, count: usize) -> usize {
+        let active_len = self.components.other_component(&mut self.graph, count);
+        (self.packing).follow_active(&self.graph, self.components.aside(active_len));
+        self.packing.follow_active(&self.graph, &self.graph.active);
+        active_len
+    }
+
+    // activate the last `count` vertices that were set aside again
+    pub(crate) fn all_components(&mut self, count: usize) {
+        let vertices = self.components.aside(count).to_vec();
+        self.components.all_components(&mut self.graph, count);
+        self.packing.follow_active(&self.graph, &vertices);
+    }
+
+    // run `update` and add its time to the statistics, the clock is only read when they ask for it
+    fn time_packing<T, F: FnOnce(&mut Self) -> T>(&mut self, update: F) -> T {
+        if !self.config.time_packing {
+            return update(self);
         }
-        if self.packing.lower + cost < self.upper {
-            self.upper -= cost;
-            if cfg!(feature = "branch-comp") {
-                self.search_components()
-            } else {
-                self.search_graph();
+        let start = Instant::now();
+        let result = update(self);
+        self.stats.packing_time += start.elapsed();
+        result
+    }
+
+    // the clusters of a graph without conflicts, labelled by their first active vertex
+    pub fn leaf_clusters(&self) -> Vec<usize> {
+        self.graph.check_easy();
+        let mut clusters = vec![usize::MAX; self.graph.vertex_merged.len()];
+        for &v1 in &self.graph.active {
+            if clusters[v1] == usize::MAX {
+                clusters[v1] = v1;
+                for v2 in self.graph.positive(v1) {
+                    clusters[v2] = v1;
+                }
             }
-            self.upper += cost;
         }
-        self.packing.remove_edge(&self.graph, v1, v2);
-        self.graph.un_cut(v1, v2, edge);
-        self.packing.add_edge(&self.graph, v1, v2);
+        clusters
     }
 
-    pub fn search_graph(&mut self) {
-        match self.best_edge() {
-            EdgeMod::Merge(v1, v2) => {
-                self.search_merge(v1, v2);
-                self.search_cut(v1, v2)
+    // go below a merge or cut that cost `cost`, `ascend` comes back up
+    fn descend(&mut self, cost: u32) {
+        self.spent += cost;
+        self.depth += 1;
+        self.stats.max_depth = max(self.stats.max_depth, self.depth);
+    }
+
+    fn ascend(&mut self, cost: u32) {
+        self.depth -= 1;
+        self.spent -= cost;
+    }
+
+    // what is searched below a merge or cut
+    fn next_step(&self) -> Step {
+        if self.config.branch_components {
+            Step::Components
+        } else {
+            Step::Graph
+        }
+    }
+
+    fn step_graph(&mut self, stack: &mut Vec<Step>) {
+        // the branch that is pushed last is searched first
+        match self.node() {
+            Some(EdgeMod::Merge(v1, v2)) => {
+                stack.push(Step::Cut(v1, v2));
+                stack.push(Step::Merge(v1, v2));
             }
-            EdgeMod::Cut(v1, v2) => {
-                self.search_cut(v1, v2);
-                self.search_merge(v1, v2)
+            Some(EdgeMod::Cut(v1, v2)) => {
+                stack.push(Step::Merge(v1, v2));
+                stack.push(Step::Cut(v1, v2));
             }
-            EdgeMod::Nothing => {
-                // println!("{}", upper);
-                self.best.clone_from(&self.graph);
-                self.best.check_easy();
-                finish_solve(&mut self.best);
-                self.upper = self.packing.lower
+            _ => {}
+        }
+    }
+
+    // the pair to branch on at the current node, `None` when the node is pruned and `Nothing`
+    // when it is a leaf, whose solution is then stored
+    fn node(&mut self) -> Option<EdgeMod> {
+        if self.should_stop() || !self.sync_upper() {
+            return None;
+        }
+        if let Improvement::Every(nodes) = self.config.improve_packing {
+            if self.stats.nodes.is_multiple_of(nodes.max(1)) {
+                self.packing.improve(&self.graph);
+            }
+        }
+        match self.fit_lower() {
+            Some(lower) if self.packing.lower.saturating_add(lower) < self.upper => {}
+            _ => return None,
+        }
+        self.stats.nodes += 1;
+        let edge = match self.best_edge() {
+            EdgeMod::Nothing => self.fit_edge(),
+            edge => edge,
+        };
+        if let EdgeMod::Nothing = edge {
+            // println!("{}", upper);
+            let clusters = self.leaf_clusters();
+            self.store_best(&clusters);
+            self.upper = self.packing.lower;
+            if self.pending == 0 {
+                self.improved(self.spent + self.upper);
             }
         }
+        Some(edge)
     }
 }
@@ -0,0 +1,77 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolverStats {
+    // calls to `search_graph`
+    pub nodes: u64,
+    pub merges: u64,
+    pub cuts: u64,
+    // merges and cuts on the deepest path
+    pub max_depth: usize,
+    // branches skipped because the lower bound reached the upper bound
+    pub pruned_merge: u64,
+    pub pruned_cut: u64,
+    // the remaining components were skipped because the first one did not fit in the budget
+    pub pruned_components: u64,
+    // searches that split the graph into more than one component
+    pub component_splits: u64,
+    // time since `Solver::new` and the cost of every new best solution
+    pub improvements: Vec<(Duration, u32)>,
+    // lower bound of the input graph
+    pub root_lower: u32,
+    // time spent updating the packing in `search_merge` and `search_cut`, with
+    // `SolverConfig::time_packing`
+    pub packing_time: Duration,
+}
+
+impl SolverStats {
+    // add the statistics of a task in a parallel search
+    pub fn add(&mut self, other: &SolverStats) {
+        self.nodes += other.nodes;
+        self.merges += other.merges;
+        self.cuts += other.cuts;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.pruned_merge += other.pruned_merge;
+        self.pruned_cut += other.pruned_cut;
+        self.pruned_components += other.pruned_components;
+        self.component_splits += other.component_splits;
+        self.improvements.extend_from_slice(&other.improvements);
+        self.packing_time += other.packing_time;
+    }
+
+    pub fn write_json<F: Write>(&self, mut file: F) -> io::Result<()> {
+        writeln!(file, "{{")?;
+        writeln!(file, "  \"nodes\": {},", self.nodes)?;
+        writeln!(file, "  \"merges\": {},", self.merges)?;
+        writeln!(file, "  \"cuts\": {},", self.cuts)?;
+        writeln!(file, "  \"max_depth\": {},", self.max_depth)?;
+        writeln!(file, "  \"pruned\": {{")?;
+        writeln!(file, "    \"merge\": {},", self.pruned_merge)?;
+        writeln!(file, "    \"cut\": {},", self.pruned_cut)?;
+        writeln!(file, "    \"components\": {}", self.pruned_components)?;
+        writeln!(file, "  }},")?;
+        writeln!(file, "  \"component_splits\": {},", self.component_splits)?;
+        writeln!(file, "  \"root_lower\": {},", self.root_lower)?;
+        writeln!(
+            file,
+            "  \"packing_seconds\": {},",
+            self.packing_time.as_secs_f64()
+        )?;
+        write!(file, "  \"improvements\": [")?;
+        for (i, (time, upper)) in self.improvements.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(
+                file,
+                "{}{{\"seconds\": {}, \"upper\": {}}}",
+                separator,
+                time.as_secs_f64(),
+                upper
+            )?;
+        }
+        writeln!(file, "]")?;
+        writeln!(file, "}}")
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::{self, Display},
+    io::{self, BufRead, BufReader, Read},
+};
+
+use crate::{
+    config::ProblemVariant,
+    graph::{Edge, Graph},
+    matrix::Storage,
+};
+
+#[derive(Debug)]
+pub enum Violation {
+    Io(io::Error),
+    Malformed { line: usize, text: String },
+    OutOfRange { line: usize, text: String },
+    SelfLoop { line: usize, text: String },
+    EditedTwice { line: usize, text: String },
+    // the vertices end up in the same cluster without being adjacent
+    NotClique { v1: usize, v2: usize },
+    // a clustering with a label for a different number of vertices
+    WrongLength { expected: usize, found: usize },
+    // the vertices are merged or linked, but in different clusters
+    Separated { v1: usize, v2: usize },
+    // the pair is forbidden, but in one cluster
+    Joined { v1: usize, v2: usize },
+    // the number or size of the clusters does not fit the bounds of the solver
+    Bounds,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Io(err) => write!(f, "could not read solution: {}", err),
+            Violation::Malformed { line, text } => {
+                write!(f, "line {}: expected `u v`, found `{}`", line, text)
+            }
+            Violation::OutOfRange { line, text } => {
+                write!(f, "line {}: vertex out of range in `{}`", line, text)
+            }
+            Violation::SelfLoop { line, text } => {
+                write!(f, "line {}: self loop `{}`", line, text)
+            }
+            Violation::EditedTwice { line, text } => {
+                write!(f, "line {}: pair `{}` is edited twice", line, text)
+            }
+            Violation::NotClique { v1, v2 } => write!(
+                f,
+                "{} and {} are connected but not adjacent, the result is not a cluster graph",
+                v1, v2
+            ),
+            Violation::WrongLength { expected, found } => {
+                write!(
+                    f,
+                    "expected clusters for {} vertices, found {}",
+                    expected, found
+                )
+            }
+            Violation::Separated { v1, v2 } => {
+                write!(f, "{} and {} have to be in the same cluster", v1, v2)
+            }
+            Violation::Joined { v1, v2 } => {
+                write!(f, "{} and {} can not be in the same cluster", v1, v2)
+            }
+            Violation::Bounds => write!(
+                f,
+                "the clusters do not fit the bounds on their number or size"
+            ),
+        }
+    }
+}
+
+impl Error for Violation {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Violation::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Violation {
+    fn from(err: io::Error) -> Self {
+        Violation::Io(err)
+    }
+}
+
+impl<S: Storage<Edge>> Graph<S> {
+    // the labels of a clustering of the original vertices for the vertices they are merged into.
+    // it has to keep merged vertices together, forbidden pairs apart and for completion the edges
+    // together
+    pub fn root_labels(
+        &self,
+        clusters: &[usize],
+        variant: ProblemVariant,
+    ) -> Result<Vec<usize>, Violation> {
+        let size = self.vertex_merged.len() / 2;
+        if clusters.len() != size {
+            return Err(Violation::WrongLength {
+                expected: size,
+                found: clusters.len(),
+            });
+        }
+        // with the original vertex that gave the label
+        let mut labels = vec![usize::MAX; self.vertex_merged.len()];
+        let mut first = vec![0; self.vertex_merged.len()];
+        let mut ids = HashMap::new();
+        for (v, id) in clusters.iter().enumerate() {
+            let root = self.root(v);
+            let label = *ids.entry(id).or_insert(v);
+            if labels[root] == usize::MAX {
+                labels[root] = label;
+                first[root] = v;
+            } else if labels[root] != label {
+                return Err(Violation::Separated {
+                    v1: first[root] + 1,
+                    v2: v + 1,
+                });
+            }
+        }
+        // only the edited pairs can be forbidden and joined, or edges that are separated
+        let mut violation = None;
+        self.for_each_edit(
+            |v| labels[v],
+            |v1, v2, edge| {
+                let (v1, v2) = (first[v1] + 1, first[v2] + 1);
+                if violation.is_none() && edge.fixed {
+                    violation = Some(Violation::Joined { v1, v2 });
+                }
+                if violation.is_none() && edge.weight > 0 && variant == ProblemVariant::Completion {
+                    violation = Some(Violation::Separated { v1, v2 });
+                }
+            },
+        );
+        match violation {
+            Some(violation) => Err(violation),
+            None => Ok(labels),
+        }
+    }
+}
+
+// apply the modifications, as written by `write_solution`, to the input graph and check that the
+// result is a disjoint union of cliques. returns the cost of the modifications
+pub fn verify<S: Storage<Edge>, F: Read>(
+    input: &Graph<S>,
+    modifications: F,
+) -> Result<u32, Violation> {
+    let size = input.vertex_merged.len() / 2;
+    let edited = read_pairs(size, modifications)?;
+    let cost = edited.iter().fold(0u32, |cost, &(v1, v2)| {
+        cost.saturating_add(input[[v1, v2]].weight.unsigned_abs())
+    });
+    let adjacent = |v1: usize, v2: usize| {
+        (input[[v1, v2]].weight > 0) != edited.contains(&(v1.min(v2), v1.max(v2)))
+    };
+    clusters(size, adjacent)?;
+    Ok(cost)
+}
+
+// the pairs `u v` of vertices numbered from 1, as pairs numbered from 0 with the smaller first
+pub(crate) fn read_pairs<F: Read>(
+    size: usize,
+    file: F,
+) -> Result<HashSet<(usize, usize)>, Violation> {
+    let mut pairs = HashSet::new();
+    for (i, text) in BufReader::new(file).lines().enumerate() {
+        let (line, text) = (i + 1, text?);
+        let text = text.trim();
+        let words: Vec<_> = text.split_whitespace().collect();
+        let (v1, v2) = match words[..] {
+            [] => continue,
+            [word1, word2] => match (word1.parse::<usize>(), word2.parse::<usize>()) {
+                (Ok(v1), Ok(v2)) => (v1, v2),
+                _ => {
+                    return Err(Violation::Malformed {
+                        line,
+                        text: text.to_owned(),
+                    })
+                }
+            },
+            _ => {
+                return Err(Violation::Malformed {
+                    line,
+                    text: text.to_owned(),
+                })
+            }
+        };
+        if !(1..=size).contains(&v1) || !(1..=size).contains(&v2) {
+            return Err(Violation::OutOfRange {
+                line,
+                text: text.to_owned(),
+            });
+        }
+        if v1 == v2 {
+            return Err(Violation::SelfLoop {
+                line,
+                text: text.to_owned(),
+            });
+        }
+        let (v1, v2) = (v1.min(v2) - 1, v1.max(v2) - 1);
+        if !pairs.insert((v1, v2)) {
+            return Err(Violation::EditedTwice {
+                line,
+                text: text.to_owned(),
+            });
+        }
+    }
+    Ok(pairs)
+}
+
+// every connected component of the result has to be a clique, labels the vertices like
+// `disk::read_clustering`
+pub(crate) fn clusters<A: Fn(usize, usize) -> bool>(
+    size: usize,
+    adjacent: A,
+) -> Result<Vec<usize>, Violation> {
+    let mut clusters = vec![usize::MAX; size];
+    let mut visited = vec![false; size];
+    for v1 in 0..size {
+        if visited[v1] {
+            continue;
+        }
+        visited[v1] = true;
+        let mut members = vec![v1];
+        let mut next = 0;
+        while let Some(&v2) = members.get(next) {
+            next += 1;
+            for (v3, visited) in visited.iter_mut().enumerate() {
+                if !*visited && adjacent(v2, v3) {
+                    *visited = true;
+                    members.push(v3);
+                }
+            }
+        }
+        for (i, &v2) in members.iter().enumerate() {
+            clusters[v2] = v1;
+            for &v3 in &members[i + 1..] {
+                if !adjacent(v2, v3) {
+                    return Err(Violation::NotClique {
+                        v1: v2 + 1,
+                        v2: v3 + 1,
+                    });
+                }
+            }
+        }
+    }
+    Ok(clusters)
+}
@@ -1,15 +1,338 @@
-use std::io::{stdin, stdout};
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs::File,
+    io::{stdin, stdout},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use cluster_lib::{
-    disk::{load, write_solution},
+    config::{Branching, ClusterCount, Improvement, LpBound, ProblemVariant, SolverConfig},
+    decompose::{solve_components, solve_components_from},
+    disk::{load_any, load_constraints, read_clustering, write_solution, Instance},
+    graph::{Edge, Graph},
+    heuristic::{clustering_cost, improve},
+    kernel::Kernel,
+    matrix::Storage,
     search::Solver,
+    verify::verify,
 };
+use signal_hook::{consts::SIGTERM, flag};
 
 extern crate cluster_lib;
 
 fn main() {
-    let graph = load(stdin()).unwrap();
-    let mut solution = Solver::new(graph);
-    solution.search_components();
-    write_solution(&solution.graph, &mut solution.best, stdout()).unwrap();
+    let mut args: Vec<_> = env::args().skip(1).collect();
+    let threads = match take_option(&mut args, "--threads").map(|threads| threads.parse()) {
+        None => 1,
+        Some(Ok(threads)) => threads,
+        Some(Err(_)) => return usage(),
+    };
+    let variant = match (
+        take_flag(&mut args, "--deletion"),
+        take_flag(&mut args, "--completion"),
+    ) {
+        (false, false) => ProblemVariant::Editing,
+        (true, false) => ProblemVariant::Deletion,
+        (false, true) => ProblemVariant::Completion,
+        (true, true) => return usage(),
+    };
+    let exactly = take_option(&mut args, "--clusters").map(|count| count.parse());
+    let at_most = take_option(&mut args, "--max-clusters").map(|count| count.parse());
+    let cluster_count = match (exactly, at_most) {
+        (None, None) => ClusterCount::Any,
+        (Some(Ok(count)), None) => ClusterCount::Exactly(count),
+        (None, Some(Ok(count))) => ClusterCount::AtMost(count),
+        _ => return usage(),
+    };
+    let max_cluster_size = match take_option(&mut args, "--max-size").map(|size| size.parse()) {
+        None => None,
+        Some(Ok(size)) => Some(size),
+        Some(Err(_)) => return usage(),
+    };
+    let lp_bound = match take_option(&mut args, "--lp").as_deref() {
+        None => LpBound::Off,
+        Some("root") => LpBound::Root,
+        Some("nodes") => LpBound::Nodes,
+        Some(_) => return usage(),
+    };
+    let improve_packing = match take_option(&mut args, "--improve").as_deref() {
+        None => Improvement::Off,
+        Some("root") => Improvement::Root,
+        Some(nodes) => match nodes.parse() {
+            Ok(nodes) if nodes > 0 => Improvement::Every(nodes),
+            _ => return usage(),
+        },
+    };
+    let branching = match take_option(&mut args, "--branching").as_deref() {
+        None | Some("conflicts") => Branching::MostConflicts,
+        Some("vertex") => Branching::Vertex,
+        Some("bound") => Branching::LowerBound,
+        Some(seed) => match seed.parse() {
+            Ok(seed) => Branching::Random(seed),
+            Err(_) => return usage(),
+        },
+    };
+    let stats = take_option(&mut args, "--stats");
+    let config = SolverConfig {
+        variant,
+        branching,
+        lp_bound,
+        improve_packing,
+        cluster_count,
+        max_cluster_size,
+        deepening: take_flag(&mut args, "--deepening"),
+        threads,
+        time_packing: stats.is_some(),
+        ..SolverConfig::default()
+    };
+    let constraints = take_option(&mut args, "--constraints");
+    let components = take_flag(&mut args, "--components");
+    let initial = take_option(&mut args, "--initial");
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] if components => match or_exit(load_any(stdin())) {
+            Instance::Dense(graph) => exact_components(graph, config, stats, constraints, initial),
+            Instance::Sparse(graph) => exact_components(graph, config, stats, constraints, initial),
+        },
+        [] => match or_exit(load_any(stdin())) {
+            Instance::Dense(graph) => exact(graph, config, stats, constraints, initial),
+            Instance::Sparse(graph) => exact(graph, config, stats, constraints, initial),
+        },
+        ["heuristic"] => heuristic_any(None),
+        ["heuristic", seconds] => match seconds.parse() {
+            Ok(seconds) => heuristic_any(Some(Duration::from_secs_f64(seconds))),
+            Err(_) => usage(),
+        },
+        ["verify", instance, solution] => match or_exit(load_any(open(instance))) {
+            Instance::Dense(graph) => verify_solution(&graph, solution),
+            Instance::Sparse(graph) => verify_solution(&graph, solution),
+        },
+        _ => usage(),
+    }
+}
+
+// remove `flag` from the arguments, returns whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
+}
+
+// remove `option` and its value from the arguments
+fn take_option(args: &mut Vec<String>, option: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == option)?;
+    if i + 1 == args.len() {
+        usage();
+    }
+    args.remove(i);
+    Some(args.remove(i))
+}
+
+fn usage() {
+    eprintln!("usage: submission [options] < instance.gr");
+    eprintln!("       submission heuristic [seconds] < instance.gr");
+    eprintln!("       submission verify instance.gr solution.s");
+    eprintln!("options:");
+    eprintln!("  --deepening         solve decision problems for increasing costs");
+    eprintln!("  --threads n         search on n threads");
+    eprintln!("  --lp root|nodes     bound by the lp relaxation at the root or every node");
+    eprintln!("  --improve root|n    swap triples of the packing at the root, and every n nodes");
+    eprintln!("  --branching rule    conflicts, vertex, bound, or a seed to break ties randomly");
+    eprintln!("  --deletion          only delete edges");
+    eprintln!("  --completion        only insert edges");
+    eprintln!("  --clusters n        exactly n clusters");
+    eprintln!("  --max-clusters n    at most n clusters");
+    eprintln!("  --max-size n        at most n vertices in a cluster");
+    eprintln!("  --components        solve the components of the kernel separately");
+    eprintln!("  --stats stats.json  write search statistics");
+    eprintln!("  --constraints file  lines `must u v` and `cannot u v` for pairs of vertices");
+    eprintln!("  --initial file      start from a clustering, a line `u v` for every joined pair");
+    process::exit(2);
+}
+
+// the value, or print the error and exit, for input that can not be parsed
+fn or_exit<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    })
+}
+
+// the file at `path`, or print why it can not be opened and exit
+fn open(path: &str) -> File {
+    or_exit(File::open(path).map_err(|err| format!("{}: {}", path, err)))
+}
+
+// reduce the instance by the constraints in the file and the kernel, with the clustering to start
+// from. one that splits what the kernel merged is moved onto the kernel, every merged vertex into
+// the cluster of most of its vertices. when that still joins what the kernel forbade, the search
+// starts without it
+fn reduce<S: Storage<Edge>>(
+    graph: &Graph<S>,
+    config: SolverConfig,
+    constraints: Option<String>,
+    initial: Option<Vec<usize>>,
+) -> (Kernel<S>, Option<Vec<usize>>) {
+    let mut constrained = graph.clone();
+    if let Some(constraints) = constraints {
+        let constraints = or_exit(load_constraints(graph, open(&constraints)));
+        if let Err(contradiction) = constrained.constrain(&constraints) {
+            eprintln!("{}", contradiction);
+            process::exit(1);
+        }
+    }
+    // a start that breaks the constraints is an error of the input, not of the kernel
+    if let Some(clusters) = &initial {
+        or_exit(constrained.root_labels(clusters, config.variant));
+    }
+    let kernel = Kernel::for_config(constrained, &config);
+    let initial = initial.and_then(|clusters| {
+        if kernel.fits(&clusters, config.variant) {
+            return Some(clusters);
+        }
+        let moved = majority(&kernel.graph, &clusters);
+        if kernel.fits(&moved, config.variant) {
+            return Some(moved);
+        }
+        eprintln!("the initial clustering does not fit the kernel, the search starts without it");
+        None
+    });
+    (kernel, initial)
+}
+
+// every original vertex goes to the cluster that most of the vertices merged with it are in, the
+// smaller label on ties
+fn majority<S: Storage<Edge>>(graph: &Graph<S>, clusters: &[usize]) -> Vec<usize> {
+    let mut labels: Vec<_> = (clusters.iter().enumerate())
+        .map(|(v, &label)| (graph.root(v), label))
+        .collect();
+    labels.sort_unstable();
+    let mut chosen = HashMap::new();
+    for run in labels.chunk_by(|pair1, pair2| pair1 == pair2) {
+        let (root, label) = run[0];
+        let best = chosen.entry(root).or_insert((0, label));
+        if run.len() > best.0 {
+            *best = (run.len(), label);
+        }
+    }
+    (0..clusters.len())
+        .map(|v| chosen[&graph.root(v)].1)
+        .collect()
+}
+
+// the clustering in the file `initial`, for the vertices of `graph`
+fn read_initial<S: Storage<Edge>>(graph: &Graph<S>, initial: Option<String>) -> Option<Vec<usize>> {
+    let size = graph.vertex_merged.len() / 2;
+    initial.map(|initial| or_exit(read_clustering(size, open(&initial))))
+}
+
+// `stats` is a file for the search statistics as json
+fn exact<S: Storage<Edge>>(
+    graph: Graph<S>,
+    config: SolverConfig,
+    stats: Option<String>,
+    constraints: Option<String>,
+    initial: Option<String>,
+) {
+    let clusters = read_initial(&graph, initial);
+    let (kernel, clusters) = reduce(&graph, config, constraints, clusters);
+    let mut solution = Solver::new(kernel.graph.clone(), config);
+    if let Some(clusters) = &clusters {
+        solution = or_exit(solution.with_initial_solution(clusters));
+    }
+    solution.solve();
+    fits(solution.upper);
+    write_solution(&graph, &kernel.lift(&solution.best), stdout()).unwrap();
+    if let Some(stats) = stats {
+        let file = or_exit(File::create(&stats).map_err(|err| format!("{}: {}", stats, err)));
+        solution.stats.write_json(file).unwrap();
+    }
+}
+
+// solve the components of the kernel separately
+fn exact_components<S: Storage<Edge>>(
+    graph: Graph<S>,
+    config: SolverConfig,
+    stats: Option<String>,
+    constraints: Option<String>,
+    initial: Option<String>,
+) {
+    let clusters = read_initial(&graph, initial);
+    let (kernel, clusters) = reduce(&graph, config, constraints, clusters);
+    let (best, cost, solver_stats) = match &clusters {
+        Some(clusters) => or_exit(solve_components_from(&kernel.graph, config, clusters)),
+        None => solve_components(&kernel.graph, config),
+    };
+    fits(cost);
+    write_solution(&graph, &kernel.lift(&best), stdout()).unwrap();
+    if let Some(stats) = stats {
+        let file = or_exit(File::create(&stats).map_err(|err| format!("{}: {}", stats, err)));
+        solver_stats.write_json(file).unwrap();
+    }
+}
+
+// the solvers leave the cost at `u32::MAX` when no clustering fits the constraints, the variant
+// and the bounds on the clusters
+fn fits(cost: u32) {
+    if cost == u32::MAX {
+        eprintln!("no clustering fits the constraints, the variant and the bounds on the clusters");
+        process::exit(1);
+    }
+}
+
+fn verify_solution<S: Storage<Edge>>(graph: &Graph<S>, solution: &str) {
+    match verify(graph, open(solution)) {
+        Ok(cost) => println!("OK {}", cost),
+        Err(violation) => {
+            println!("{}", violation);
+            process::exit(1);
+        }
+    }
+}
+
+fn heuristic_any(time: Option<Duration>) {
+    match or_exit(load_any(stdin())) {
+        Instance::Dense(graph) => heuristic(graph, time),
+        Instance::Sparse(graph) => heuristic(graph, time),
+    }
+}
+
+// improve a clustering until SIGTERM or the time budget, then write the best one
+fn heuristic<S: Storage<Edge> + 'static>(graph: Graph<S>, time: Option<Duration>) {
+    let graph = Arc::new(graph);
+    let stop = Arc::new(AtomicBool::new(false));
+    flag::register(SIGTERM, Arc::clone(&stop)).unwrap();
+
+    // every vertex in a cluster of its own is there to write before the first clustering is done
+    let singletons: Vec<_> = (0..graph.vertex_merged.len()).collect();
+    let cost = clustering_cost(&graph, &singletons);
+    let best = Arc::new(Mutex::new((singletons, cost)));
+    {
+        let (graph, stop, best) = (Arc::clone(&graph), Arc::clone(&stop), Arc::clone(&best));
+        thread::spawn(move || {
+            improve(&graph, 0, &stop, |clusters, cost| {
+                let mut best = best.lock().unwrap();
+                if cost < best.1 {
+                    *best = (clusters.to_vec(), cost);
+                }
+            })
+        });
+    }
+
+    let deadline = time.map(|time| Instant::now() + time);
+    while !stop.load(Ordering::Relaxed) && deadline.is_none_or(|d| Instant::now() < d) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    stop.store(true, Ordering::Relaxed);
+
+    let clusters = &best.lock().unwrap().0;
+    write_solution(&graph, &Graph::from_clusters(&graph, clusters), stdout()).unwrap();
+    process::exit(0);
 }